@@ -0,0 +1,594 @@
+use crate::{
+    error::CliError,
+    output::{BatchEntry, OutputFormat, Printer},
+    BatchArgs,
+};
+use md5::{Digest, Md5};
+use root_s3::{BulkMode, BulkOp, BulkOptions, BulkResult, CopyOptions, ObjectLocation};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{collections::HashSet, path::Path};
+
+/// Format of a manifest, taken from its extension when not given
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+    /// Comma separated values, with a header line naming the columns
+    Csv,
+    /// A JSON object per line
+    Ndjson,
+}
+
+/// Operation of a manifest line
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchOp {
+    Copy,
+    Tag,
+    Delete,
+    Restore,
+}
+
+/// The file written with --report, read back to rerun the lines that failed
+#[derive(Serialize, Deserialize, Debug)]
+struct BatchReport {
+    /// Manifest the line numbers refer to
+    manifest: String,
+    /// MD5 of the content of the manifest, lines only match while it is unchanged
+    manifest_md5: String,
+    lines: Vec<BatchEntry>,
+}
+
+impl BatchReport {
+    // Replace the results of the lines that were run again
+    fn update(&mut self, entries: &[BatchEntry]) {
+        let run = entries
+            .iter()
+            .map(|entry| entry.line)
+            .collect::<HashSet<_>>();
+        self.lines.retain(|entry| !run.contains(&entry.line));
+        self.lines.extend(entries.iter().cloned());
+        self.lines.sort_by_key(|entry| entry.line);
+    }
+}
+
+// A manifest line, or why it could not be read
+type ManifestLine = (usize, Result<Map<String, Value>, String>);
+
+pub async fn run(
+    client: &root_s3::Client,
+    args: BatchArgs,
+    project_id: Option<i32>,
+    format: OutputFormat,
+) -> Result<(), CliError> {
+    let manifest = args.manifest.display().to_string();
+    let content = tokio::fs::read_to_string(&args.manifest)
+        .await
+        .map_err(|e| CliError::file(&manifest, e))?;
+    let lines = match args
+        .manifest_format
+        .map_or_else(|| format_of(&args.manifest), Ok)?
+    {
+        ManifestFormat::Csv => csv_lines(&content)?,
+        ManifestFormat::Ndjson => ndjson_lines(&content),
+    };
+
+    // Continue from the report of an earlier run
+    let manifest_md5 = hex::encode(Md5::digest(&content));
+    let mut report = match &args.report {
+        Some(path) if path.exists() => read_report(path, &manifest, &manifest_md5).await?,
+        _ => BatchReport {
+            manifest,
+            manifest_md5,
+            lines: Vec::new(),
+        },
+    };
+    let done = report
+        .lines
+        .iter()
+        .filter(|entry| entry.status != "failed")
+        .map(|entry| entry.line)
+        .collect::<HashSet<_>>();
+
+    let mut entries = Vec::new();
+    let mut ops = Vec::new();
+    for (line, fields) in lines {
+        if done.contains(&line) {
+            continue;
+        }
+        match fields.and_then(|fields| bulk_op(&fields, args.op, project_id)) {
+            Ok(op) => ops.push((line, op)),
+            Err(error) => entries.push(invalid_entry(line, error)),
+        }
+    }
+
+    // An invalid line is the first failure
+    let not_run = if args.fail_fast && !entries.is_empty() {
+        ops.len()
+    } else {
+        let options = BulkOptions {
+            concurrency: args.concurrency,
+            retries: args.retries,
+            mode: if args.fail_fast {
+                BulkMode::FailFast
+            } else {
+                BulkMode::ContinueOnError
+            },
+            ..BulkOptions::default()
+        };
+        let line_numbers = ops.iter().map(|(line, _)| *line).collect::<Vec<_>>();
+        let bulk = client
+            .run_bulk(
+                tokio_stream::iter(ops.into_iter().map(|(_, op)| op)),
+                &options,
+            )
+            .await;
+
+        entries.extend(
+            bulk.results
+                .iter()
+                .map(|item| batch_entry(line_numbers[item.index], item)),
+        );
+        line_numbers.len() - bulk.results.len()
+    };
+    entries.sort_by_key(|entry| entry.line);
+
+    if let Some(path) = &args.report {
+        report.update(&entries);
+        tokio::fs::write(path, serde_json::to_vec_pretty(&report)?)
+            .await
+            .map_err(|e| CliError::file(path.display(), e))?;
+    }
+
+    // Only print what this run did, the report file has the earlier runs
+    let mut printer = Printer::new(format);
+    for entry in &entries {
+        printer.item(entry);
+    }
+    printer.finish();
+
+    let failed = entries
+        .iter()
+        .filter(|entry| entry.status == "failed")
+        .count();
+    if failed > 0 || not_run > 0 {
+        let message = format!("{failed} of {} lines failed", entries.len() + not_run);
+        let message = if not_run > 0 {
+            format!("{message}, {not_run} not run after the first failure")
+        } else {
+            message
+        };
+        let mut error = CliError::partial("running batch", message);
+        if args.report.is_some() {
+            error.report.hint = Some("run the same command again to rerun only these lines");
+        }
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+// The report at `path`, which has to be of the same content as the manifest: the manifest may
+// have been moved, but lines numbers of an edited one would point at other lines
+async fn read_report(
+    path: &Path,
+    manifest: &str,
+    manifest_md5: &str,
+) -> Result<BatchReport, CliError> {
+    let mut report: BatchReport = serde_json::from_slice(
+        &tokio::fs::read(path)
+            .await
+            .map_err(|e| CliError::file(path.display(), e))?,
+    )?;
+    if report.manifest_md5 != manifest_md5 {
+        return Err(CliError::usage(
+            "running batch",
+            format!(
+                "{} is the report of another manifest ({} when it was written), not of {manifest} as it is now",
+                path.display(),
+                report.manifest
+            ),
+        ));
+    }
+
+    manifest.clone_into(&mut report.manifest);
+    Ok(report)
+}
+
+fn format_of(path: &Path) -> Result<ManifestFormat, CliError> {
+    let extension = path
+        .extension()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+
+    match extension.as_str() {
+        "csv" => Ok(ManifestFormat::Csv),
+        "ndjson" | "jsonl" | "json" => Ok(ManifestFormat::Ndjson),
+        _ => Err(CliError::usage(
+            "reading manifest",
+            format!(
+                "can not tell the format of {} from its extension, pass --manifest-format",
+                path.display()
+            ),
+        )),
+    }
+}
+
+fn ndjson_lines(content: &str) -> Vec<ManifestLine> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let fields = serde_json::from_str::<Map<String, Value>>(line)
+                .map_err(|e| format!("invalid JSON object: {e}"));
+            (i + 1, fields)
+        })
+        .collect()
+}
+
+// Lines of a CSV manifest as objects with a string per column of the header
+fn csv_lines(content: &str) -> Result<Vec<ManifestLine>, CliError> {
+    let mut lines = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+
+    let Some((_, header)) = lines.next() else {
+        return Ok(Vec::new());
+    };
+    let columns = csv_fields(header)
+        .map_err(|e| CliError::usage("reading manifest", format!("invalid header: {e}")))?
+        .into_iter()
+        .map(|column| column.trim().to_lowercase())
+        .collect::<Vec<_>>();
+
+    Ok(lines
+        .map(|(i, line)| {
+            let fields = csv_fields(line).and_then(|fields| {
+                if fields.len() > columns.len() {
+                    return Err(format!(
+                        "{} fields, the header has {} columns",
+                        fields.len(),
+                        columns.len()
+                    ));
+                }
+                Ok(columns
+                    .iter()
+                    .cloned()
+                    .zip(fields.into_iter().map(Value::String))
+                    .collect())
+            });
+            (i + 1, fields)
+        })
+        .collect())
+}
+
+// Fields of a CSV line, which may be quoted with `"` and contain `""` for a quote
+fn csv_fields(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quote".to_string());
+    }
+
+    fields.push(field);
+    Ok(fields)
+}
+
+// The operation of a manifest line, `op` when the line has none
+fn bulk_op(
+    fields: &Map<String, Value>,
+    op: Option<BatchOp>,
+    project_id: Option<i32>,
+) -> Result<BulkOp, String> {
+    let text = |name: &str| match fields.get(name) {
+        Some(Value::String(value)) if !value.is_empty() => Some(value.clone()),
+        Some(Value::Number(value)) => Some(value.to_string()),
+        _ => None,
+    };
+
+    let op = match text("op") {
+        Some(name) => <BatchOp as clap::ValueEnum>::from_str(&name, true)
+            .map_err(|_| format!("unknown op {name:?}, expected copy, tag, delete or restore"))?,
+        None => op.ok_or("no op, add an op column or pass --op")?,
+    };
+    let bucket = text("bucket").ok_or("no bucket")?;
+    let key = text("key").ok_or("no key")?;
+    let location = ObjectLocation::new(&bucket, &key, project_id);
+
+    Ok(match op {
+        BatchOp::Copy => {
+            let (to_bucket, to_key) = (text("to_bucket"), text("to_key"));
+            if to_bucket.is_none() && to_key.is_none() {
+                return Err("copy needs a to_bucket or to_key".to_string());
+            }
+            BulkOp::Copy {
+                source: location,
+                destination: ObjectLocation::new(
+                    to_bucket.unwrap_or(bucket),
+                    to_key.unwrap_or(key),
+                    project_id,
+                ),
+                options: CopyOptions::default(),
+            }
+        }
+        BatchOp::Tag => {
+            let tags = match fields.get("tags") {
+                Some(Value::Object(tags)) => tags
+                    .iter()
+                    .map(|(name, value)| match value {
+                        Value::String(value) => Ok((name.clone(), value.clone())),
+                        _ => Err(format!("tag {name:?} is not a string")),
+                    })
+                    .collect::<Result<_, _>>()?,
+                _ => crate::parse_metadata(&text("tags").ok_or("tag needs tags")?)?,
+            };
+            BulkOp::Tag { location, tags }
+        }
+        BatchOp::Delete => BulkOp::Delete { location },
+        BatchOp::Restore => {
+            let days = text("days")
+                .map(|days| days.parse::<i32>())
+                .transpose()
+                .map_err(|_| "days has to be a number")?
+                .unwrap_or(1);
+            BulkOp::Restore { location, days }
+        }
+    })
+}
+
+fn invalid_entry(line: usize, error: String) -> BatchEntry {
+    BatchEntry {
+        line,
+        status: "failed".to_string(),
+        op: None,
+        source: None,
+        target: None,
+        code: Some("InvalidLine".to_string()),
+        error: Some(error),
+    }
+}
+
+fn batch_entry(line: usize, item: &BulkResult) -> BatchEntry {
+    let url = |location: &ObjectLocation| format!("s3://{}/{}", location.bucket, location.key);
+    let source = match &item.op {
+        BulkOp::Copy { source, .. } => Some(url(source)),
+        _ => None,
+    };
+    let (status, code, error) = match &item.result {
+        Ok(_) => {
+            let status = match item.op.name() {
+                "copy" => "copied",
+                "tag" => "tagged",
+                "delete" => "deleted",
+                "restore" => "restored",
+                other => other,
+            };
+            (status.to_string(), None, None)
+        }
+        Err(e) => {
+            let code = e.code().map_or_else(
+                || CliError::from_root("", e).report.kind.to_string(),
+                str::to_string,
+            );
+            ("failed".to_string(), Some(code), Some(e.to_string()))
+        }
+    };
+
+    BatchEntry {
+        line,
+        status,
+        op: Some(item.op.name().to_string()),
+        source,
+        target: Some(url(item.op.location())),
+        code,
+        error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(json: Value) -> Map<String, Value> {
+        match json {
+            Value::Object(fields) => fields,
+            _ => unreachable!(),
+        }
+    }
+
+    fn entry(line: usize, status: &str) -> BatchEntry {
+        BatchEntry {
+            line,
+            status: status.to_string(),
+            op: None,
+            source: None,
+            target: None,
+            code: None,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn csv_fields_cases() {
+        let cases = [
+            ("a,b,c", Ok(vec!["a", "b", "c"])),
+            ("a,,c\r", Ok(vec!["a", "", "c"])),
+            ("", Ok(vec![""])),
+            (r#"a,"b,c",d"#, Ok(vec!["a", "b,c", "d"])),
+            (r#""say ""hi""",b"#, Ok(vec![r#"say "hi""#, "b"])),
+            (r#""""#, Ok(vec![""])),
+            (r#"a"b,c"#, Ok(vec![r#"a"b"#, "c"])),
+            (r#"a,"b,c"#, Err("unterminated quote")),
+            (r#""a"""#, Err("unterminated quote")),
+        ];
+        for (line, expected) in cases {
+            let expected = expected
+                .map(|fields| fields.into_iter().map(str::to_string).collect::<Vec<_>>())
+                .map_err(str::to_string);
+            assert_eq!(csv_fields(line), expected, "{line}");
+        }
+    }
+
+    #[test]
+    fn csv_lines_name_fields_after_the_header() {
+        let lines = csv_lines("Bucket, KEY\n\nb,k\nb\nb,k,extra\n\"b,k\n").unwrap();
+
+        let numbers = lines.iter().map(|(line, _)| *line).collect::<Vec<_>>();
+        assert_eq!(numbers, [3, 4, 5, 6]);
+        assert_eq!(
+            lines[0].1,
+            Ok(fields(serde_json::json!({ "bucket": "b", "key": "k" })))
+        );
+        assert_eq!(lines[1].1, Ok(fields(serde_json::json!({ "bucket": "b" }))));
+        assert_eq!(
+            lines[2].1,
+            Err("3 fields, the header has 2 columns".to_string())
+        );
+        assert_eq!(lines[3].1, Err("unterminated quote".to_string()));
+
+        assert!(csv_lines("").unwrap().is_empty());
+        assert!(csv_lines("\"bucket\n").is_err());
+    }
+
+    #[test]
+    fn bulk_op_takes_the_op_of_the_line_or_the_default() {
+        let line = fields(serde_json::json!({ "bucket": "b", "key": "k" }));
+        assert_eq!(
+            bulk_op(&line, None, None).unwrap_err(),
+            "no op, add an op column or pass --op"
+        );
+        assert!(matches!(
+            bulk_op(&line, Some(BatchOp::Delete), None),
+            Ok(BulkOp::Delete { .. })
+        ));
+
+        let line = fields(serde_json::json!({ "op": "Delete", "bucket": "b", "key": "k" }));
+        assert!(matches!(
+            bulk_op(&line, Some(BatchOp::Copy), None),
+            Ok(BulkOp::Delete { .. })
+        ));
+
+        let line = fields(serde_json::json!({ "op": "move", "bucket": "b", "key": "k" }));
+        assert_eq!(
+            bulk_op(&line, Some(BatchOp::Delete), None).unwrap_err(),
+            "unknown op \"move\", expected copy, tag, delete or restore"
+        );
+    }
+
+    #[test]
+    fn bulk_op_cases() {
+        let expected_tags = std::collections::HashMap::from([
+            ("team".to_string(), "data".to_string()),
+            ("env".to_string(), "prod".to_string()),
+        ]);
+        for tags in [
+            serde_json::json!("team=data,env=prod"),
+            serde_json::json!({ "team": "data", "env": "prod" }),
+        ] {
+            let line =
+                fields(serde_json::json!({ "op": "tag", "bucket": "b", "key": "k", "tags": tags }));
+            match bulk_op(&line, None, None) {
+                Ok(BulkOp::Tag { tags, .. }) => assert_eq!(tags, expected_tags),
+                other => panic!("{other:?}"),
+            }
+        }
+
+        let cases = [
+            (
+                serde_json::json!({ "op": "tag", "bucket": "b", "key": "k" }),
+                "tag needs tags",
+            ),
+            (
+                serde_json::json!({ "op": "tag", "bucket": "b", "key": "k", "tags": { "n": 1 } }),
+                "tag \"n\" is not a string",
+            ),
+            (
+                serde_json::json!({ "op": "copy", "bucket": "b", "key": "k" }),
+                "copy needs a to_bucket or to_key",
+            ),
+            (
+                serde_json::json!({ "op": "restore", "bucket": "b", "key": "k", "days": "x" }),
+                "days has to be a number",
+            ),
+            (
+                serde_json::json!({ "op": "delete", "key": "k" }),
+                "no bucket",
+            ),
+            (
+                serde_json::json!({ "op": "delete", "bucket": "b", "key": "" }),
+                "no key",
+            ),
+        ];
+        for (line, error) in cases {
+            assert_eq!(
+                bulk_op(&fields(line.clone()), None, None).unwrap_err(),
+                error,
+                "{line}"
+            );
+        }
+
+        let line = fields(
+            serde_json::json!({ "op": "copy", "bucket": "b", "key": "k", "to_bucket": "c" }),
+        );
+        match bulk_op(&line, None, Some(1)) {
+            Ok(BulkOp::Copy {
+                source,
+                destination,
+                ..
+            }) => {
+                assert_eq!((source.bucket, source.key), ("b".into(), "k".into()));
+                assert_eq!(
+                    (destination.bucket, destination.key),
+                    ("c".into(), "k".into())
+                );
+                assert_eq!(destination.project.project_id, Some(1));
+            }
+            other => panic!("{other:?}"),
+        }
+        let line =
+            fields(serde_json::json!({ "op": "restore", "bucket": "b", "key": 12, "days": 3 }));
+        match bulk_op(&line, None, None) {
+            Ok(BulkOp::Restore { location, days }) => {
+                assert_eq!((location.key, days), ("12".into(), 3));
+            }
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[test]
+    fn update_replaces_the_lines_run_again() {
+        let mut report = BatchReport {
+            manifest: "manifest.csv".to_string(),
+            manifest_md5: String::new(),
+            lines: vec![entry(1, "copied"), entry(2, "failed"), entry(4, "failed")],
+        };
+
+        report.update(&[entry(4, "deleted"), entry(3, "failed"), entry(2, "failed")]);
+
+        let lines = report
+            .lines
+            .iter()
+            .map(|entry| (entry.line, entry.status.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [(1, "copied"), (2, "failed"), (3, "failed"), (4, "deleted")]
+        );
+    }
+}
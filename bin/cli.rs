@@ -1,8 +1,29 @@
-use anyhow::Result;
-use clap::{arg, command, Parser};
+use aws_sdk_s3::{
+    primitives::{DateTime, DateTimeFormat},
+    types::{MetadataDirective, ObjectStorageClass, StorageClass, TaggingDirective},
+};
+use clap::Parser;
+use config::ConfigCommand;
+use error::{failed, CliError};
 use log::debug;
-use std::collections::HashMap;
-use tokio::{fs::File, io::AsyncReadExt};
+use output::{
+    print_one, ActionResult, BucketEntry, HeadEntry, MoveEntry, ObjectEntry, OutputFormat, Printer,
+    SyncEntry, UploadEntry,
+};
+use progress::Bar;
+use std::{collections::HashMap, io::IsTerminal, process::ExitCode};
+use tokio_stream::StreamExt;
+
+mod batch;
+mod config;
+mod error;
+mod output;
+mod progress;
+
+const DEFAULT_URL: &str = "http://localhost:9000";
+
+/// Path standing for stdin or stdout
+const STDIO: &str = "-";
 
 #[derive(Parser, Debug)]
 #[clap(name = "Root S3 cli", version = "0.1", about = "S3 cli")]
@@ -10,29 +31,62 @@ pub struct S3Cli {
     #[clap(subcommand)]
     command: SubCommand,
 
-    #[clap(
-        long,
-        value_name = "URL",
-        required = false,
-        default_value = "http://localhost:9000",
-        short = 'u'
-    )]
-    url: String,
+    /// Url of the Root instance [default: `http://localhost:9000`]
+    #[clap(long, value_name = "URL", short = 'u', env = "ROOT_S3_URL")]
+    url: Option<String>,
 
-    #[clap(long, short, required = false)]
+    #[clap(long, short, required = false, env = "ROOT_S3_ORG_ID")]
     org_id: Option<i32>,
 
-    #[clap(long, short, required = false)]
+    #[clap(long, short, required = false, env = "ROOT_S3_PROJECT_ID")]
     project_id: Option<i32>,
 
-    #[clap(long, required = false)]
+    /// API key, `API_KEY` is read as well when neither this nor --access-key is given
+    #[clap(
+        long,
+        required = false,
+        env = "ROOT_S3_API_KEY",
+        hide_env_values = true
+    )]
     api_key: Option<String>,
 
-    #[clap(long, required = false)]
+    #[clap(long, required = false, env = "ROOT_S3_ACCESS_KEY")]
     access_key: Option<String>,
 
-    #[clap(long, short, required = false)]
+    #[clap(
+        long,
+        short,
+        required = false,
+        env = "ROOT_S3_SECRET_KEY",
+        hide_env_values = true
+    )]
     secret_key: Option<String>,
+
+    /// Put the bucket in the path ("path") or in the host ("virtual-hosted") [default: path]
+    #[clap(long, required = false, env = "ROOT_S3_ADDRESSING_STYLE")]
+    addressing_style: Option<root_s3::AddressingStyle>,
+
+    /// Limit uploads and downloads together to this many bytes per second, like "500K" or "10M"
+    #[clap(long, value_name = "RATE", value_parser = parse_rate, env = "ROOT_S3_LIMIT_RATE")]
+    limit_rate: Option<u64>,
+
+    /// Profile of the config file to take the settings above from, flags and environment
+    /// variables take precedence over it
+    #[clap(long, global = true, env = "ROOT_S3_PROFILE")]
+    profile: Option<String>,
+
+    /// Format of the results, errors are printed as JSON on stderr with json, ndjson and yaml
+    #[clap(long, global = true, value_enum, default_value_t)]
+    format: OutputFormat,
+
+    /// Do not draw progress bars, which are only drawn when stderr is a terminal
+    #[clap(long, short, global = true)]
+    quiet: bool,
+
+    /// Abort a multipart upload that fails instead of keeping its parts on the server to
+    /// continue it later
+    #[clap(long, global = true)]
+    no_resume: bool,
 }
 
 #[derive(Parser, Debug)] // requires `derive` feature
@@ -44,213 +98,1047 @@ pub enum SubCommand {
     // Objects
     PutObject(PutObjectArgs),
     GetObject(GetObjectArgs),
-    CopyObject(CopyObjectArgs),
+    /// Write the content of objects to stdout, e.g. `cat s3://bucket/a.csv | head`
+    Cat(CatArgs),
+    /// Copy between local files and objects, e.g. `cp ./a.txt s3://bucket/a.txt`
+    Cp(CopyArgs),
     DeleteObject(DeleteObjectArgs),
+    /// Move an object, or all objects under a prefix with --recursive
+    Mv(MoveArgs),
+    UpdateMetadata(UpdateMetadataArgs),
     ListObjects(ListObjectArgs),
+    /// List the objects and "directories" under `s3://bucket/prefix`
+    Ls(LsArgs),
+    /// Search the objects under `s3://bucket/prefix` by name, size, date or storage class
+    Find(FindArgs),
     GetHeadObject(GetHeadObject),
+    /// Upload the new and changed files of a directory to `s3://bucket/prefix`, or download
+    /// the new and changed objects of a prefix to a directory
+    Sync(SyncArgs),
+    /// Replicate the objects under `s3://bucket/prefix` to another bucket, project or Root
+    /// instance, copying only what changed since the last run
+    Mirror(MirrorArgs),
+    /// Continue the uploads of files that failed halfway, or list or clean them up
+    Resume(ResumeArgs),
+    /// Copy, tag, delete or restore the objects listed in a CSV or NDJSON manifest
+    Batch(BatchArgs),
+    /// Manage the profiles of the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
 }
 
 #[tokio::main]
-#[allow(clippy::too_many_lines)]
-async fn main() -> std::io::Result<()> {
+async fn main() -> ExitCode {
     env_logger::init();
     debug!("cli started");
     let args: S3Cli = S3Cli::parse();
+    let format = args.format;
 
-    let client = get_client(&args).expect("Error creating client");
+    match Box::pin(run(args)).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            e.report.print(format);
+            e.exit()
+        }
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+async fn run(mut args: S3Cli) -> Result<(), CliError> {
+    let format = args.format;
+    // The config is managed without a client
+    if let SubCommand::Config { command } = &args.command {
+        return config::run(command, format);
+    }
+
+    config::apply(&mut args)?;
+    let client = get_client(&args)?;
+    // The destination of a mirror falls back to the settings of the source, taken apart below
+    let mirror_target = match &args.command {
+        SubCommand::Mirror(MirrorArgs { target, .. }) => {
+            Some(mirror_target(&args, target, &client)?)
+        }
+        _ => None,
+    };
 
     match args.command {
         SubCommand::CreateBucket(CreateBucketArgs { name }) => {
             let res = client.create_bucket(&name, args.project_id).await;
             match res {
-                Ok(_) => println!("Bucket created: {name:?}"),
-                Err(e) => eprintln!("Error creating bucket: {e:?}"),
+                Ok(_) => print_one(format, &ActionResult::new("created", name)),
+                Err(e) => return Err(CliError::from_root("creating bucket", &e)),
             }
         }
         SubCommand::DeleteBucket(DeleteBucketArgs { name }) => {
             let res = client.delete_bucket(&name, args.project_id).await;
             match res {
-                Ok(_) => println!("Bucket deleted: {name:?}"),
-                Err(e) => eprintln!("Error deleting bucket: {e:?}"),
+                Ok(_) => print_one(format, &ActionResult::new("deleted", name)),
+                Err(e) => return Err(CliError::from_root("deleting bucket", &e)),
             }
         }
         SubCommand::ListBuckets(ListBucketsArgs {}) => {
-            let res = client.list_buckets(args.project_id).await.unwrap();
+            let res = match client.list_buckets(args.project_id).await {
+                Ok(res) => res,
+                Err(e) => return Err(CliError::from_root("listing buckets", &e)),
+            };
 
             debug!("result {:?}", res);
 
-            if let Some(buckets) = res.buckets {
-                println!("\nBuckets:\n");
-                for bucket in buckets {
-                    println!(
-                        "- Bucket:\n\tname: {:?}\n\tcreated at: {:?}",
-                        bucket.name.unwrap(),
-                        bucket.creation_date.unwrap().secs()
-                    );
-                }
-                println!("\n");
-            } else {
-                println!("No buckets");
+            let mut printer = Printer::new(format);
+            for bucket in res.buckets() {
+                printer.item(&BucketEntry::from(bucket));
             }
+            printer.finish();
         }
         SubCommand::PutObject(PutObjectArgs {
             bucket,
             key,
             file_path,
-            metadata,
+            object,
         }) => {
-            let mut file = File::open(file_path).await?;
-
-            // Create a buffer to store the file contents
-            let mut buffer = Vec::new();
-
-            let metadata_map = if let Some(metadata) = metadata {
-                let mut map = HashMap::new();
-                metadata.split(',').for_each(|m| {
-                    let mut split = m.split('=');
-                    let key = split.next().unwrap();
-                    let value = split.next().unwrap();
-                    map.insert(key.to_string(), value.to_string());
-                });
-                Some(map)
+            let location = root_s3::ObjectLocation::new(&bucket, &key, args.project_id);
+            let res = if file_path == STDIO {
+                // Streamed through a multipart upload, the length is not known up front
+                let bar = Bar::new(args.quiet);
+                let res = client
+                    .upload_reader(
+                        &location,
+                        bar.wrap(tokio::io::stdin(), None),
+                        object.into_put_options(),
+                    )
+                    .await
+                    .map_err(failed("creating object"))?;
+                bar.finish();
+                res
             } else {
-                None
+                upload_file(
+                    &client,
+                    std::path::Path::new(&file_path),
+                    &location,
+                    object.into_put_options(),
+                    args.quiet,
+                    !args.no_resume,
+                    "creating object",
+                )
+                .await?
             };
 
-            // Read the entire file into the buffer
-            file.read_to_end(&mut buffer).await?;
-            log::debug!("buffer size: {}", buffer.len());
-
-            let res = client
-                .put_object(&bucket, &key, buffer.into(), args.project_id, metadata_map)
-                .await;
-
-            match res {
-                Ok(r) => println!(
-                    "Object created: {:?} in bucket {:?}",
-                    r.e_tag.unwrap(),
-                    bucket
-                ),
-                Err(e) => eprintln!("Error creating object: {e:?}"),
-            }
+            print_one(
+                format,
+                &ActionResult::new("uploaded", format!("s3://{bucket}/{key}"))
+                    .source(file_path)
+                    .e_tag(res.e_tag)
+                    .size(Some(res.size)),
+            );
         }
         SubCommand::GetObject(GetObjectArgs {
             bucket,
             key,
             output,
+            range,
+            if_match,
+            if_none_match,
+            headers,
         }) => {
-            let res = client.get_object(&bucket, &key, args.project_id).await;
+            let options = root_s3::GetOptions {
+                range,
+                if_match,
+                if_none_match,
+                headers,
+            };
+            if output == STDIO {
+                let res = client
+                    .get_object(&bucket, &key, args.project_id, options)
+                    .await
+                    .map_err(failed("getting object"))?;
 
-            match res {
-                Ok(res) => {
-                    // Write content to output file
-                    let mut body = res.body.into_async_read();
-                    let mut file = File::create(&output).await?;
-                    tokio::io::copy(&mut body, &mut file).await?;
-
-                    println!(
-                        "Object with id '{}' downloaded to {}, size: {} bytes",
-                        key,
-                        output,
-                        res.content_length.unwrap()
+                // Only the content goes to stdout, there is no result to print. No bar when
+                // the content is written to the terminal as well.
+                let bar = Bar::new(args.quiet || std::io::stdout().is_terminal());
+                let total = res.content_length.and_then(|len| u64::try_from(len).ok());
+                write_stdout(bar.wrap(res.body.into_async_read(), total)).await?;
+                bar.finish();
+                return Ok(());
+            }
+
+            let res = download_file(
+                &client,
+                &root_s3::ObjectLocation::new(&bucket, &key, args.project_id),
+                std::path::Path::new(&output),
+                options,
+                args.quiet,
+                "getting object",
+            )
+            .await?;
+
+            print_one(
+                format,
+                &ActionResult::new("downloaded", output)
+                    .source(format!("s3://{bucket}/{key}"))
+                    .e_tag(res.e_tag)
+                    .size(Some(res.size)),
+            );
+        }
+        SubCommand::Cat(CatArgs { objects }) => {
+            for object in objects {
+                let Location::S3 { bucket, key } = object else {
+                    return Err(CliError::usage(
+                        "getting object",
+                        "pass s3://bucket/key urls",
+                    ));
+                };
+
+                let res = client
+                    .get_object(
+                        &bucket,
+                        &key,
+                        args.project_id,
+                        root_s3::GetOptions::default(),
+                    )
+                    .await
+                    .map_err(failed("getting object"))?;
+                write_stdout(res.body.into_async_read()).await?;
+            }
+        }
+        SubCommand::Cp(CopyArgs {
+            source,
+            destination,
+            object,
+            metadata_directive,
+            tagging_directive,
+        }) => {
+            if let Location::S3 { key, .. } = &source {
+                if key.is_empty() || key.ends_with('/') {
+                    return Err(CliError::usage(
+                        "copying",
+                        "the source has to be an object, not a bucket or prefix",
+                    ));
+                }
+            }
+
+            match (source, destination) {
+                (Location::Local(path), Location::S3 { bucket, key }) => {
+                    let key = key_for(&key, &path);
+                    let res = upload_file(
+                        &client,
+                        &path,
+                        &root_s3::ObjectLocation::new(&bucket, &key, args.project_id),
+                        object.into_put_options(),
+                        args.quiet,
+                        !args.no_resume,
+                        "uploading object",
+                    )
+                    .await?;
+
+                    print_one(
+                        format,
+                        &ActionResult::new("uploaded", format!("s3://{bucket}/{key}"))
+                            .source(path.display().to_string())
+                            .e_tag(res.e_tag)
+                            .size(Some(res.size)),
+                    );
+                }
+                (Location::S3 { bucket, key }, Location::Local(path)) => {
+                    let path = path_for(path, &key);
+                    let res = download_file(
+                        &client,
+                        &root_s3::ObjectLocation::new(&bucket, &key, args.project_id),
+                        &path,
+                        root_s3::GetOptions::default(),
+                        args.quiet,
+                        "downloading object",
+                    )
+                    .await?;
+
+                    print_one(
+                        format,
+                        &ActionResult::new("downloaded", path.display().to_string())
+                            .source(format!("s3://{bucket}/{key}"))
+                            .e_tag(res.e_tag)
+                            .size(Some(res.size)),
                     );
                 }
-                Err(e) => eprintln!("Error getting object: {e:?}"),
+                (
+                    Location::S3 { bucket, key },
+                    Location::S3 {
+                        bucket: target_bucket,
+                        key: target_key,
+                    },
+                ) => {
+                    let target_key = key_for(&target_key, std::path::Path::new(&key));
+                    let options = root_s3::CopyOptions {
+                        metadata_directive: metadata_directive
+                            .as_deref()
+                            .map(MetadataDirective::from),
+                        tagging_directive: tagging_directive.as_deref().map(TaggingDirective::from),
+                        ..object.into_copy_options()
+                    };
+                    // Objects above 5 GiB are copied with a multipart copy
+                    let res = client
+                        .copy(
+                            &root_s3::ObjectLocation::new(&bucket, &key, args.project_id),
+                            &root_s3::ObjectLocation::new(
+                                &target_bucket,
+                                &target_key,
+                                args.project_id,
+                            ),
+                            options,
+                        )
+                        .await;
+                    match res {
+                        Ok(res) => print_one(
+                            format,
+                            &ActionResult::new(
+                                "copied",
+                                format!("s3://{target_bucket}/{target_key}"),
+                            )
+                            .source(format!("s3://{bucket}/{key}"))
+                            .e_tag(res.e_tag),
+                        ),
+                        Err(e) => return Err(CliError::from_root("copying object", &e)),
+                    }
+                }
+                (Location::Local(_), Location::Local(_)) => {
+                    return Err(CliError::usage(
+                        "copying",
+                        "either the source or the destination has to be an s3:// url",
+                    ));
+                }
             }
         }
-        SubCommand::CopyObject(CopyObjectArgs {
-            bucket,
-            key,
-            source_bucket,
-            source_key,
+        SubCommand::DeleteObject(DeleteObjectArgs { bucket, key }) => {
+            let res = client.delete_object(&bucket, &key, args.project_id).await;
+            match res {
+                Ok(_) => print_one(
+                    format,
+                    &ActionResult::new("deleted", format!("s3://{bucket}/{key}")),
+                ),
+                Err(e) => return Err(CliError::from_root("deleting object", &e)),
+            }
+        }
+        SubCommand::Mv(MoveArgs {
+            source,
+            destination,
+            recursive,
+            concurrency,
+            report,
         }) => {
+            let (
+                Location::S3 { bucket, key },
+                Location::S3 {
+                    bucket: target_bucket,
+                    key: target_key,
+                },
+            ) = (source, destination)
+            else {
+                return Err(CliError::usage(
+                    "moving",
+                    "mv only moves objects, both sides have to be s3:// urls",
+                ));
+            };
+            let target_key = if recursive {
+                target_key
+            } else {
+                key_for(&target_key, std::path::Path::new(&key))
+            };
+
+            let source = root_s3::ObjectLocation::new(&bucket, &key, args.project_id);
+            let destination =
+                root_s3::ObjectLocation::new(&target_bucket, &target_key, args.project_id);
+
+            if !recursive {
+                let res = client
+                    .move_object(&source, &destination, root_s3::CopyOptions::default())
+                    .await;
+                match res {
+                    Ok(res) => print_one(
+                        format,
+                        &ActionResult::new("moved", format!("s3://{target_bucket}/{target_key}"))
+                            .source(format!("s3://{bucket}/{key}"))
+                            .e_tag(res.e_tag),
+                    ),
+                    Err(e) => return Err(CliError::from_root("moving object", &e)),
+                }
+                return Ok(());
+            }
+
+            // Continue from the report of an earlier run
+            let mut move_report = match &report {
+                Some(path) if std::path::Path::new(path).exists() => serde_json::from_slice(
+                    &tokio::fs::read(path)
+                        .await
+                        .map_err(|e| CliError::file(path, e))?,
+                )?,
+                _ => root_s3::MoveReport::default(),
+            };
+            let already_moved = move_report.moved.len();
+
             let res = client
-                .copy_object(&bucket, &key, &source_bucket, &source_key, args.project_id)
+                .move_prefix(&source, &destination, concurrency, &mut move_report)
                 .await;
 
-            match res {
-                Ok(res) => {
-                    println!("{res:?}");
-                    println!("Object copied: {key:?} to bucket {bucket:?}");
-                }
-                Err(e) => eprintln!("Error copying object: {e:?}"),
+            if let Some(path) = &report {
+                tokio::fs::write(path, serde_json::to_vec_pretty(&move_report)?).await?;
+            }
+
+            // Only print what this run did, the report file has the earlier runs
+            let mut printer = Printer::new(format);
+            for moved in &move_report.moved[already_moved..] {
+                printer.item(&MoveEntry {
+                    source: format!("s3://{bucket}/{}", moved.source_key),
+                    destination: Some(format!("s3://{target_bucket}/{}", moved.destination_key)),
+                    status: "moved",
+                    error: None,
+                });
+            }
+            for failed in &move_report.failed {
+                printer.item(&MoveEntry {
+                    source: format!("s3://{bucket}/{}", failed.source_key),
+                    destination: None,
+                    status: "failed",
+                    error: Some(failed.error.clone()),
+                });
+            }
+            printer.finish();
+
+            res.map_err(failed("moving objects"))?;
+            if !move_report.failed.is_empty() {
+                return Err(CliError::partial(
+                    "moving objects",
+                    format!("{} objects could not be moved", move_report.failed.len()),
+                ));
             }
         }
-        SubCommand::DeleteObject(DeleteObjectArgs { bucket, key }) => {
-            let res = client.delete_object(&bucket, &key, args.project_id).await;
+        SubCommand::UpdateMetadata(UpdateMetadataArgs {
+            bucket,
+            key,
+            metadata,
+        }) => {
+            let res = client
+                .update_metadata(&bucket, &key, metadata, args.project_id)
+                .await;
             match res {
-                Ok(_) => println!("Object with id '{key}' deleted"),
-                Err(e) => eprintln!("Error deleting object: {e:?}"),
+                Ok(res) => print_one(
+                    format,
+                    &ActionResult::new("updated", format!("s3://{bucket}/{key}"))
+                        .e_tag(res.copy_object_result.and_then(|r| r.e_tag)),
+                ),
+                Err(e) => return Err(CliError::from_root("updating metadata", &e)),
             }
         }
         SubCommand::ListObjects(ListObjectArgs { bucket, prefix }) => {
+            let res = match client.list_objects(&bucket, &prefix, args.project_id).await {
+                Ok(res) => res,
+                Err(e) => return Err(CliError::from_root("listing objects", &e)),
+            };
+
+            let mut printer = Printer::new(format);
+            for object in res.contents() {
+                printer.item(&ObjectEntry::object(object, ""));
+            }
+            printer.finish();
+        }
+        SubCommand::Ls(LsArgs { target, recursive }) => {
+            let Location::S3 {
+                bucket,
+                key: prefix,
+            } = target
+            else {
+                return Err(CliError::usage(
+                    "listing objects",
+                    "pass an s3://bucket/prefix url",
+                ));
+            };
+
+            let mut printer = Printer::new(format);
+            if recursive {
+                let mut objects = client.list_objects_stream(&bucket, &prefix, args.project_id);
+                while let Some(object) = objects.next().await {
+                    match object {
+                        Ok(object) => printer.item(&ObjectEntry::object(&object, "")),
+                        Err(e) => {
+                            // Keep what was listed before the failure
+                            printer.finish();
+                            return Err(CliError::from_root("listing objects", &e));
+                        }
+                    }
+                }
+                printer.finish();
+                return Ok(());
+            }
+
             let res = client
-                .list_objects(&bucket, &prefix, args.project_id)
-                .await
-                .unwrap();
-
-            if let Some(contents) = res.contents {
-                println!("Objects in bucket '{bucket}'\n");
-                for c in contents {
-                    println!(
-                        "- Object:\n\tkey: {:?}\n\tupdated at: {:?}\n\tsize: {} bytes",
-                        c.key.unwrap(),
-                        c.last_modified.unwrap().secs(),
-                        c.size.unwrap(),
-                    );
+                .list_objects_with_delimiter(&bucket, &prefix, "/", args.project_id)
+                .await;
+            let listing = match res {
+                Ok(listing) => listing,
+                Err(e) => return Err(CliError::from_root("listing objects", &e)),
+            };
+
+            for common_prefix in &listing.common_prefixes {
+                printer.item(&ObjectEntry::prefix(common_prefix, &prefix));
+            }
+            for object in &listing.objects {
+                printer.item(&ObjectEntry::object(object, &prefix));
+            }
+            printer.finish();
+        }
+        SubCommand::Find(find) => {
+            let Location::S3 {
+                ref bucket,
+                key: ref prefix,
+            } = find.target
+            else {
+                return Err(CliError::usage(
+                    "finding objects",
+                    "pass an s3://bucket/prefix url",
+                ));
+            };
+
+            let filter = match find.filter() {
+                Ok(filter) => filter,
+                Err(e) => return Err(CliError::from_root("parsing filter", &e)),
+            };
+            let mut objects = client.find_objects(bucket, prefix, args.project_id, filter);
+
+            // Without sorting, print the objects as they are listed
+            let mut printer = Printer::new(format);
+            let mut found = Vec::new();
+            while let Some(object) = objects.next().await {
+                match object {
+                    Ok(object) if find.sort.is_none() => {
+                        printer.item(&ObjectEntry::object(&object, ""));
+                    }
+                    Ok(object) => found.push(object),
+                    Err(e) => {
+                        printer.finish();
+                        return Err(CliError::from_root("listing objects", &e));
+                    }
                 }
-                println!("\n");
-            } else {
-                println!("No objects in bucket '{bucket}'");
             }
+
+            if let Some(sort) = find.sort {
+                root_s3::sort_objects(&mut found, sort);
+                if find.reverse {
+                    found.reverse();
+                }
+                for object in &found {
+                    printer.item(&ObjectEntry::object(object, ""));
+                }
+            }
+            printer.finish();
+        }
+        SubCommand::Resume(resume_args) => resume(&client, resume_args, format, args.quiet).await?,
+        SubCommand::Batch(batch_args) => {
+            batch::run(&client, batch_args, args.project_id, format).await?;
         }
+        SubCommand::Config { .. } => {}
         SubCommand::GetHeadObject(GetHeadObject { bucket, key }) => {
-            let res = client
-                .head_object(&bucket, &key, args.project_id)
+            let res = match client.head_object(&bucket, &key, args.project_id).await {
+                Ok(res) => res,
+                Err(e) => return Err(CliError::from_root("getting head object", &e)),
+            };
+
+            print_one(format, &HeadEntry::new(&bucket, &key, &res));
+        }
+        SubCommand::Mirror(MirrorArgs {
+            source,
+            destination,
+            target: _,
+            delete,
+            checksum,
+            include,
+            exclude,
+            dry_run,
+            concurrency,
+            report,
+        }) => {
+            let (
+                Location::S3 { bucket, key },
+                Location::S3 {
+                    bucket: target_bucket,
+                    key: target_key,
+                },
+            ) = (source, destination)
+            else {
+                return Err(CliError::usage(
+                    "mirroring",
+                    "both sides have to be s3:// urls, use sync for local directories",
+                ));
+            };
+
+            let mut options = root_s3::SyncOptions {
+                compare: if checksum {
+                    root_s3::SyncCompare::Checksum
+                } else {
+                    root_s3::SyncCompare::SizeAndTime
+                },
+                delete,
+                dry_run,
+                concurrency,
+                ..Default::default()
+            };
+            for glob in &include {
+                options = options.include(glob).map_err(failed("mirroring"))?;
+            }
+            for glob in &exclude {
+                options = options.exclude(glob).map_err(failed("mirroring"))?;
+            }
+
+            let (target_client, target_project) =
+                mirror_target.unwrap_or_else(|| (client.clone(), args.project_id));
+            let mirror_report = client
+                .mirror(
+                    &root_s3::ObjectLocation::new(&bucket, &key, args.project_id),
+                    &target_client,
+                    &root_s3::ObjectLocation::new(&target_bucket, &target_key, target_project),
+                    &options,
+                )
                 .await
-                .unwrap();
+                .map_err(failed("mirroring"))?;
+            let summary = mirror_report.summary();
+
+            if let Some(path) = &report {
+                let content = serde_json::json!({ "summary": summary, "report": mirror_report });
+                tokio::fs::write(path, serde_json::to_vec_pretty(&content)?)
+                    .await
+                    .map_err(|e| CliError::file(path, e))?;
+            }
+
+            let mut printer = Printer::new(format);
+            for copied in &mirror_report.copied {
+                printer.item(&SyncEntry {
+                    status: "copied",
+                    dry_run,
+                    source: Some(format!("s3://{bucket}/{}", copied.source_key)),
+                    target: format!("s3://{target_bucket}/{}", copied.destination_key),
+                    size: Some(copied.size),
+                    error: None,
+                });
+            }
+            for deleted in &mirror_report.deleted {
+                printer.item(&SyncEntry {
+                    status: "deleted",
+                    dry_run,
+                    source: None,
+                    target: format!("s3://{target_bucket}/{deleted}"),
+                    size: None,
+                    error: None,
+                });
+            }
+            for failed in &mirror_report.failed {
+                printer.item(&SyncEntry {
+                    status: "failed",
+                    dry_run,
+                    source: None,
+                    target: failed.key.clone(),
+                    size: None,
+                    error: Some(failed.error.clone()),
+                });
+            }
+            printer.finish();
+            // Scripts get the totals from --report
+            if !format.is_structured() {
+                eprintln!("{summary}");
+            }
 
-            println!("Object with id '{key}' in bucket '{bucket}'\n");
-            if let Some(meta) = res.metadata {
-                println!("Metadata:");
-                for (k, v) in meta {
-                    println!("\t{k}: {v}");
+            if summary.failed > 0 {
+                return Err(CliError::partial(
+                    "mirroring",
+                    format!("{} objects could not be mirrored", summary.failed),
+                ));
+            }
+        }
+        SubCommand::Sync(SyncArgs {
+            source,
+            destination,
+            delete,
+            checksum,
+            include,
+            exclude,
+            dry_run,
+            concurrency,
+            object,
+        }) => {
+            let mut options = root_s3::SyncOptions {
+                compare: if checksum {
+                    root_s3::SyncCompare::Checksum
+                } else {
+                    root_s3::SyncCompare::SizeAndTime
+                },
+                delete,
+                dry_run,
+                concurrency,
+                put_options: object.into_put_options(),
+                ..Default::default()
+            };
+            for glob in &include {
+                options = options.include(glob).map_err(failed("syncing"))?;
+            }
+            for glob in &exclude {
+                options = options.exclude(glob).map_err(failed("syncing"))?;
+            }
+
+            let (report, bucket, upload) = match (source, destination) {
+                (
+                    Location::Local(directory),
+                    Location::S3 {
+                        bucket,
+                        key: prefix,
+                    },
+                ) => {
+                    let report = client
+                        .sync_up(&directory, &bucket, &prefix, args.project_id, &options)
+                        .await;
+                    (report, bucket, true)
+                }
+                (
+                    Location::S3 {
+                        bucket,
+                        key: prefix,
+                    },
+                    Location::Local(directory),
+                ) => {
+                    let report = client
+                        .sync_down(&bucket, &prefix, args.project_id, &directory, &options)
+                        .await;
+                    (report, bucket, false)
+                }
+                _ => {
+                    return Err(CliError::usage(
+                        "syncing",
+                        "pass a local directory and an s3://bucket/prefix url, in either order",
+                    ))
                 }
+            };
+            let report = report.map_err(failed("syncing"))?;
+
+            let mut printer = Printer::new(format);
+            let entry = |status, source: Option<String>, target: String| SyncEntry {
+                status,
+                dry_run,
+                source,
+                target,
+                size: None,
+                error: None,
+            };
+            for file in &report.uploaded {
+                printer.item(&SyncEntry {
+                    size: Some(file.size),
+                    ..entry(
+                        "uploaded",
+                        Some(file.path.display().to_string()),
+                        format!("s3://{bucket}/{}", file.key),
+                    )
+                });
+            }
+            for file in &report.downloaded {
+                printer.item(&SyncEntry {
+                    size: Some(file.size),
+                    ..entry(
+                        "downloaded",
+                        Some(format!("s3://{bucket}/{}", file.key)),
+                        file.path.display().to_string(),
+                    )
+                });
             }
-            println!(
-                "- Object:\n\tkey: {:?}\n\tupdated at: {:?}\n\tsize: {} bytes",
-                key,
-                res.last_modified,
-                res.content_length.unwrap_or_default(),
+            for deleted in &report.deleted {
+                let target = if upload {
+                    format!("s3://{bucket}/{deleted}")
+                } else {
+                    deleted.clone()
+                };
+                printer.item(&entry("deleted", None, target));
+            }
+            for failed in &report.failed {
+                printer.item(&SyncEntry {
+                    error: Some(failed.error.clone()),
+                    ..entry("failed", None, format!("s3://{bucket}/{}", failed.key))
+                });
+            }
+            printer.finish();
+
+            if !report.failed.is_empty() {
+                return Err(CliError::partial(
+                    "syncing",
+                    format!("{} files could not be synced", report.failed.len()),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Upload a file, continuing where an earlier upload of it failed. A failed upload keeps its
+// checkpoint, so the error tells how to continue it. With --no-resume it is aborted instead.
+async fn upload_file(
+    client: &root_s3::Client,
+    path: &std::path::Path,
+    destination: &root_s3::ObjectLocation,
+    options: root_s3::PutOptions,
+    quiet: bool,
+    resume: bool,
+    action: &'static str,
+) -> Result<root_s3::UploadOutcome, CliError> {
+    let bar = Bar::new(quiet);
+    if !resume {
+        let res = client
+            .upload_file(path, destination, options, bar.observer())
+            .await;
+        bar.finish();
+        return res.map_err(failed(action));
+    }
+
+    let checkpoints = root_s3::Checkpoints::new(config::checkpoint_dir());
+    let res = client
+        .upload_file_resumable(path, destination, options, &checkpoints, bar.observer())
+        .await;
+    bar.finish();
+
+    res.map_err(|e| {
+        let mut error = CliError::from_root(action, &e);
+        let resumable = std::fs::canonicalize(path)
+            .ok()
+            .and_then(|path| checkpoints.find(client, destination, &path))
+            .is_some();
+        if resumable {
+            error.report.hint = Some(
+                "run the same command again, or `resume`, to continue where the upload stopped",
             );
-            println!("\n");
         }
+        error
+    })
+}
+
+// Download an object to a file, continuing where an earlier download of it failed. A failed
+// download keeps its partial file, so the error tells how to continue it.
+async fn download_file(
+    client: &root_s3::Client,
+    source: &root_s3::ObjectLocation,
+    path: &std::path::Path,
+    options: root_s3::GetOptions,
+    quiet: bool,
+    action: &'static str,
+) -> Result<root_s3::DownloadOutcome, CliError> {
+    let bar = Bar::new(quiet);
+    let res = client
+        .download_file(source, path, options, bar.observer())
+        .await;
+    bar.finish();
+
+    res.map_err(|e| {
+        let mut error = CliError::from_root(action, &e);
+        if root_s3::download::partial_path(path).exists() {
+            error.report.hint = Some("run the same command again to continue the download");
+        }
+        error
+    })
+}
+
+// Continue the unfinished uploads of this url, or list or clean up the checkpoints
+async fn resume(
+    client: &root_s3::Client,
+    args: ResumeArgs,
+    format: OutputFormat,
+    quiet: bool,
+) -> Result<(), CliError> {
+    let checkpoints = root_s3::Checkpoints::new(config::checkpoint_dir());
+    let all = checkpoints.list().map_err(failed("listing uploads"))?;
+    let mut printer = Printer::new(format);
+
+    if args.list {
+        for checkpoint in &all {
+            let status = if checkpoint.matches_file() {
+                "pending"
+            } else {
+                "stale"
+            };
+            printer.item(&UploadEntry::new(status, checkpoint));
+        }
+        printer.finish();
+        return Ok(());
+    }
+
+    // The uploads to another Root instance or organisation need a client for it
+    let (ours, others): (Vec<_>, Vec<_>) = all
+        .into_iter()
+        .partition(|checkpoint| client.started_upload(checkpoint));
+    if !others.is_empty() {
+        log::info!(
+            "skipping {} uploads to other urls or organisations than {}",
+            others.len(),
+            client.endpoint_url
+        );
+    }
+
+    let mut failures = 0;
+    for checkpoint in ours {
+        let entry = if args.clean {
+            let old = args.older_than.is_some_and(|date| {
+                i64::try_from(checkpoint.started).unwrap_or(i64::MAX) < date.secs()
+            });
+            if checkpoint.matches_file() && !old {
+                continue;
+            }
+
+            match client.discard_upload(&checkpoint, &checkpoints).await {
+                Ok(()) => UploadEntry::new("discarded", &checkpoint),
+                Err(e) => UploadEntry::new("failed", &checkpoint).error(e),
+            }
+        } else {
+            let bar = Bar::new(quiet);
+            let res = client
+                .resume_upload(checkpoint.clone(), &checkpoints, bar.observer())
+                .await;
+            bar.finish();
+
+            match res {
+                Ok(outcome) => UploadEntry {
+                    uploaded: outcome.size,
+                    ..UploadEntry::new("uploaded", &checkpoint)
+                },
+                Err(e) => UploadEntry::new("failed", &checkpoint).error(e),
+            }
+        };
+
+        if entry.error.is_some() {
+            failures += 1;
+        }
+        printer.item(&entry);
     }
+    printer.finish();
 
+    if failures > 0 {
+        let action = if args.clean {
+            "cleaning up uploads"
+        } else {
+            "resuming uploads"
+        };
+        return Err(CliError::partial(
+            action,
+            format!("{failures} uploads failed"),
+        ));
+    }
     Ok(())
 }
 
-fn get_client(args: &S3Cli) -> Result<root_s3::Client> {
-    if let Some(api_key) = &args.api_key {
-        Ok(root_s3::Client::new(
-            args.url.clone(),
-            api_key,
-            args.org_id.unwrap_or(0),
-        )?)
+// Copy the content of an object to stdout, a closed pipe (e.g. `| head`) is not an error
+async fn write_stdout(mut body: impl tokio::io::AsyncRead + Unpin) -> Result<(), CliError> {
+    match tokio::io::copy(&mut body, &mut tokio::io::stdout()).await {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => std::process::exit(0),
+        res => res.map(|_| ()).map_err(CliError::from),
+    }
+}
+
+fn get_client(args: &S3Cli) -> Result<root_s3::Client, CliError> {
+    let credentials = args.access_key.as_deref().zip(args.secret_key.as_deref());
+
+    connect(
+        args.url.as_deref(),
+        args.org_id,
+        args.api_key.as_deref(),
+        credentials,
+        args.addressing_style,
+    )
+    .map(|client| match args.limit_rate {
+        Some(rate) => client.with_rate_limit(rate),
+        None => client,
+    })
+}
+
+// Client and project of the destination of a mirror: its own flags, then its profile, then the
+// settings of the source
+fn mirror_target(
+    args: &S3Cli,
+    to: &MirrorTargetArgs,
+    client: &root_s3::Client,
+) -> Result<(root_s3::Client, Option<i32>), CliError> {
+    if to.to_profile.is_none()
+        && to.to_url.is_none()
+        && to.to_org_id.is_none()
+        && to.to_api_key.is_none()
+    {
+        return Ok((client.clone(), to.to_project_id.or(args.project_id)));
+    }
+
+    let profile = match &to.to_profile {
+        Some(name) => config::profile(name)?,
+        None => config::Profile::default(),
+    };
+    let project_id = to.to_project_id.or(profile.project_id).or(args.project_id);
+    let addressing_style = match &profile.addressing_style {
+        Some(style) => Some(style.parse().map_err(failed("reading config"))?),
+        None => args.addressing_style,
+    };
+
+    let api_key = to.to_api_key.clone().or_else(|| profile.api_key());
+    let secret_key = profile.secret_key();
+    let credentials = profile.access_key.as_deref().zip(secret_key.as_deref());
+    // Without credentials of its own the destination uses those of the source
+    let (api_key, credentials) = if api_key.is_none() && credentials.is_none() {
+        (
+            args.api_key.clone(),
+            args.access_key.as_deref().zip(args.secret_key.as_deref()),
+        )
     } else {
+        (api_key, credentials)
+    };
+
+    let target = connect(
+        to.to_url
+            .as_deref()
+            .or(profile.url.as_deref())
+            .or(args.url.as_deref()),
+        to.to_org_id.or(profile.org_id).or(args.org_id),
+        api_key.as_deref(),
+        credentials,
+        addressing_style,
+    )?;
+    // Both ends of a mirror count against the same limit
+    let target = root_s3::Client {
+        rate_limits: client.rate_limits.clone(),
+        ..target
+    };
+
+    Ok((target, project_id))
+}
+
+fn connect(
+    url: Option<&str>,
+    org_id: Option<i32>,
+    api_key: Option<&str>,
+    credentials: Option<(&str, &str)>,
+    addressing_style: Option<root_s3::AddressingStyle>,
+) -> Result<root_s3::Client, CliError> {
+    let url = url.unwrap_or(DEFAULT_URL);
+
+    let client = if let Some(api_key) = api_key {
+        root_s3::Client::new(url, api_key, org_id.unwrap_or(0))
+            .map_err(failed("creating client"))?
+    } else {
+        let Some((access_key, secret_key)) = credentials else {
+            return Err(CliError::usage(
+                "creating client",
+                "pass --api-key, or --access-key and --secret-key",
+            ));
+        };
         let cred = root_s3::S3Credentials {
-            access_key_id: args.access_key.clone().unwrap(),
-            secret_access_key: args.secret_key.clone().unwrap(),
+            access_key_id: access_key.to_string(),
+            secret_access_key: secret_key.to_string(),
             session_token: None,
             expiration: None,
             region: "eu".to_string(),
         };
 
-        Ok(root_s3::Client::new_from_s3_credentials(
-            args.url.clone(),
-            cred,
-        )?)
-    }
+        root_s3::Client::new_from_s3_credentials(url, cred).map_err(failed("creating client"))?
+    };
+
+    // Unlike the library the cli addresses buckets path-style unless told otherwise, which
+    // works with local stand-ins without any DNS setup
+    Ok(client.with_addressing_style(addressing_style.unwrap_or(root_s3::AddressingStyle::Path)))
 }
 
 #[derive(clap::Args, Debug)]
@@ -280,11 +1168,12 @@ pub struct PutObjectArgs {
     #[arg(long)]
     pub key: String,
 
+    /// File to upload, "-" to stream stdin
     #[arg(long)]
     pub file_path: String,
 
-    #[arg(long)]
-    pub metadata: Option<String>,
+    #[command(flatten)]
+    pub object: ObjectArgs,
 }
 
 #[derive(clap::Args, Debug)]
@@ -296,24 +1185,202 @@ pub struct GetObjectArgs {
     #[arg(long)]
     pub key: String,
 
+    /// File to write the object to, "-" for stdout
     #[arg(long)]
     pub output: String,
+
+    /// Only download part of the object, e.g. "bytes=0-1023"
+    #[arg(long)]
+    pub range: Option<String>,
+
+    /// Only download the object if its etag matches
+    #[arg(long)]
+    pub if_match: Option<String>,
+
+    /// Only download the object if its etag does not match
+    #[arg(long)]
+    pub if_none_match: Option<String>,
+
+    /// Extra header to send with the request, can be repeated
+    #[arg(long = "header", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    pub headers: Vec<(String, String)>,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct CatArgs {
+    /// Objects to write one after the other, as `s3://bucket/key`
+    #[arg(required = true, value_parser = parse_location)]
+    pub objects: Vec<Location>,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct CopyArgs {
+    /// Local file or `s3://bucket/key` to copy
+    #[arg(value_parser = parse_location)]
+    pub source: Location,
+
+    /// Local path or `s3://bucket/key` to copy to, the file name of the source is added to a
+    /// directory or to a key ending in "/"
+    #[arg(value_parser = parse_location)]
+    pub destination: Location,
+
+    #[command(flatten)]
+    pub object: ObjectArgs,
+
+    /// Keep the metadata of the source (COPY) or use the given one (REPLACE)
+    #[arg(long, value_parser = ["COPY", "REPLACE"])]
+    pub metadata_directive: Option<String>,
+
+    /// Keep the tags of the source (COPY) or use the given ones (REPLACE)
+    #[arg(long, value_parser = ["COPY", "REPLACE"])]
+    pub tagging_directive: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct MoveArgs {
+    /// Object to move as `s3://bucket/key`, or the prefix to move with --recursive
+    #[arg(value_parser = parse_location)]
+    pub source: Location,
+
+    /// New location of the object as `s3://bucket/key`, or the prefix to move the objects to with
+    /// --recursive
+    #[arg(value_parser = parse_location)]
+    pub destination: Location,
+
+    /// Move all objects under the source prefix
+    #[arg(long)]
+    pub recursive: bool,
+
+    /// Number of objects moved at the same time with --recursive
+    #[arg(long, default_value_t = 8)]
+    pub concurrency: usize,
+
+    /// JSON file recording the moved and failed objects with --recursive, an existing report
+    /// is continued
+    #[arg(long)]
+    pub report: Option<String>,
 }
 
 #[derive(clap::Args, Debug)]
 #[command(author, version, about, long_about = None)]
-pub struct CopyObjectArgs {
+pub struct SyncArgs {
+    /// Local directory to upload, or `s3://bucket/prefix` to download
+    #[arg(value_parser = parse_location)]
+    pub source: Location,
+
+    /// `s3://bucket/prefix` to upload the files under, or the directory to download to
+    #[arg(value_parser = parse_location)]
+    pub destination: Location,
+
+    /// Delete the objects or files that are not at the source
     #[arg(long)]
-    pub bucket: String,
+    pub delete: bool,
 
+    /// Compare the MD5 of the files with the etag of the objects instead of the size and
+    /// modification time
     #[arg(long)]
-    pub key: String,
+    pub checksum: bool,
+
+    /// Only sync paths matching a glob, relative to the directory and prefix, can be repeated
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Leave paths matching a glob alone, can be repeated
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Only print what would be transferred and deleted
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Number of files transferred at the same time
+    #[arg(long, default_value_t = 8)]
+    pub concurrency: usize,
+
+    #[command(flatten)]
+    pub object: ObjectArgs,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct MirrorArgs {
+    /// `s3://bucket/prefix` to replicate
+    #[arg(value_parser = parse_location)]
+    pub source: Location,
+
+    /// `s3://bucket/prefix` to replicate to
+    #[arg(value_parser = parse_location)]
+    pub destination: Location,
+
+    #[command(flatten)]
+    pub target: MirrorTargetArgs,
+
+    /// Delete the objects under the destination prefix that are not at the source
+    #[arg(long)]
+    pub delete: bool,
 
+    /// Compare the etags of the objects instead of their size and date
     #[arg(long)]
-    pub source_bucket: String,
+    pub checksum: bool,
+
+    /// Only mirror keys matching a glob, relative to the prefix, can be repeated
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Leave keys matching a glob alone, can be repeated
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Only print what would be copied and deleted
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Number of objects copied at the same time
+    #[arg(long, default_value_t = 8)]
+    pub concurrency: usize,
+
+    /// JSON file to write the totals and every copied, deleted and failed object to
+    #[arg(long)]
+    pub report: Option<String>,
+}
+
+/// Where the destination of a mirror lives, the settings of the source are used for anything
+/// not given
+#[derive(clap::Args, Debug)]
+pub struct MirrorTargetArgs {
+    /// Profile of the config file to connect to the destination with
+    #[arg(long, value_name = "PROFILE")]
+    pub to_profile: Option<String>,
+
+    /// Url of the Root instance of the destination
+    #[arg(long, value_name = "URL")]
+    pub to_url: Option<String>,
 
     #[arg(long)]
-    pub source_key: String,
+    pub to_org_id: Option<i32>,
+
+    #[arg(long)]
+    pub to_project_id: Option<i32>,
+
+    #[arg(long, env = "ROOT_S3_TO_API_KEY", hide_env_values = true)]
+    pub to_api_key: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct UpdateMetadataArgs {
+    #[arg(long)]
+    pub bucket: String,
+
+    #[arg(long)]
+    pub key: String,
+
+    /// New metadata of the object, e.g. "owner=alice,team=data"
+    #[arg(long, value_parser = parse_metadata)]
+    pub metadata: HashMap<String, String>,
 }
 
 #[derive(clap::Args, Debug)]
@@ -335,6 +1402,93 @@ pub struct ListObjectArgs {
     pub prefix: String,
 }
 
+#[derive(clap::Args, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct LsArgs {
+    /// Bucket and prefix to list as `s3://bucket/prefix`, add a trailing "/" to list the
+    /// contents of a "directory"
+    #[arg(value_parser = parse_location)]
+    pub target: Location,
+
+    /// List all objects under the prefix instead of one level
+    #[arg(long, short)]
+    pub recursive: bool,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct FindArgs {
+    /// Bucket and prefix to search as `s3://bucket/prefix`
+    #[arg(value_parser = parse_location)]
+    pub target: Location,
+
+    /// Glob the object name (the key after the last "/") has to match, e.g. "*.csv"
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Glob the full key has to match, "*" does not match "/" but "**" does
+    #[arg(long)]
+    pub path: Option<String>,
+
+    /// Regular expression that has to match somewhere in the key
+    #[arg(long)]
+    pub regex: Option<String>,
+
+    /// Smallest size, in bytes or with a K, M, G or T suffix
+    #[arg(long, value_parser = parse_size)]
+    pub min_size: Option<i64>,
+
+    /// Largest size, in bytes or with a K, M, G or T suffix
+    #[arg(long, value_parser = parse_size)]
+    pub max_size: Option<i64>,
+
+    /// Only objects modified since a date ("2024-05-01", "2024-05-01T12:00:00Z") or a time
+    /// ago ("30m", "12h", "7d")
+    #[arg(long, value_parser = parse_date)]
+    pub newer: Option<DateTime>,
+
+    /// Only objects modified before a date or a time ago, like --newer
+    #[arg(long, value_parser = parse_date)]
+    pub older: Option<DateTime>,
+
+    /// Only objects in a storage class, e.g. "GLACIER"
+    #[arg(long)]
+    pub storage_class: Option<String>,
+
+    /// Sort the objects by "key", "size" or "date" instead of printing them as they are listed
+    #[arg(long)]
+    pub sort: Option<root_s3::SortBy>,
+
+    /// Sort in descending order
+    #[arg(long, requires = "sort")]
+    pub reverse: bool,
+}
+
+impl FindArgs {
+    fn filter(&self) -> Result<root_s3::ObjectFilter, root_s3::Error> {
+        let mut filter = root_s3::ObjectFilter {
+            min_size: self.min_size,
+            max_size: self.max_size,
+            modified_after: self.newer,
+            modified_before: self.older,
+            storage_class: self.storage_class.as_deref().map(ObjectStorageClass::from),
+            ..Default::default()
+        };
+
+        if let Some(name) = &self.name {
+            filter = filter.name_glob(name)?;
+        }
+        if let Some(path) = &self.path {
+            filter = filter.key_glob(path)?;
+        }
+        if let Some(regex) = &self.regex {
+            filter = filter.key_regex(regex)?;
+        }
+
+        Ok(filter)
+    }
+}
+
 #[derive(clap::Args, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct GetHeadObject {
@@ -344,3 +1498,407 @@ pub struct GetHeadObject {
     #[arg(long)]
     pub key: String,
 }
+
+#[derive(Parser, Debug)]
+pub struct ResumeArgs {
+    /// Only list the unfinished uploads, "stale" ones can not be resumed as their file changed
+    #[arg(long, conflicts_with = "clean")]
+    pub list: bool,
+
+    /// Abort the uploads of files that changed or are gone and remove their checkpoints,
+    /// instead of resuming uploads
+    #[arg(long)]
+    pub clean: bool,
+
+    /// With --clean, abort the uploads started before a date or a time ago (e.g. "7d") as well
+    #[arg(long, requires = "clean", value_parser = parse_date)]
+    pub older_than: Option<DateTime>,
+}
+
+#[derive(Parser, Debug)]
+pub struct BatchArgs {
+    /// Manifest with an operation per line, in the columns or fields `op` (copy, tag, delete
+    /// or restore), `bucket`, `key`, `to_bucket` and `to_key` (copy), `tags` (tag, e.g.
+    /// "team=data,env=prod") and `days` (restore, 1 when not given)
+    pub manifest: std::path::PathBuf,
+
+    /// Format of the manifest, taken from its extension (.csv, .ndjson or .jsonl) when not given
+    #[arg(long, value_enum)]
+    pub manifest_format: Option<batch::ManifestFormat>,
+
+    /// Operation of the lines without an op
+    #[arg(long, value_enum)]
+    pub op: Option<batch::BatchOp>,
+
+    /// JSON file recording the result of every line, an existing report is continued by
+    /// running only the lines that did not succeed
+    #[arg(long)]
+    pub report: Option<std::path::PathBuf>,
+
+    /// Number of lines run at the same time
+    #[arg(long, default_value_t = 8)]
+    pub concurrency: usize,
+
+    /// Number of times a line is tried again after a network error or a busy server
+    #[arg(long, default_value_t = 2)]
+    pub retries: u32,
+
+    /// Stop starting lines after the first one that failed
+    #[arg(long)]
+    pub fail_fast: bool,
+}
+
+/// Headers and metadata stored with an object
+#[derive(clap::Args, Debug)]
+pub struct ObjectArgs {
+    /// Metadata of the object, e.g. "owner=alice,team=data"
+    #[arg(long, value_parser = parse_metadata)]
+    pub metadata: Option<HashMap<String, String>>,
+
+    /// Tags of the object, e.g. "team=data,env=prod"
+    #[arg(long, value_parser = parse_metadata)]
+    pub tagging: Option<HashMap<String, String>>,
+
+    /// Content-Type of the object, e.g. "application/json"
+    #[arg(long)]
+    pub content_type: Option<String>,
+
+    /// Cache-Control returned when the object is downloaded
+    #[arg(long)]
+    pub cache_control: Option<String>,
+
+    /// Content-Disposition returned when the object is downloaded
+    #[arg(long)]
+    pub content_disposition: Option<String>,
+
+    /// Content-Encoding of the object, e.g. "gzip"
+    #[arg(long)]
+    pub content_encoding: Option<String>,
+
+    /// Storage class of the object, e.g. "STANDARD"
+    #[arg(long)]
+    pub storage_class: Option<String>,
+
+    /// Extra header to send with the request, can be repeated
+    #[arg(long = "header", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    pub headers: Vec<(String, String)>,
+}
+
+impl ObjectArgs {
+    fn into_put_options(self) -> root_s3::PutOptions {
+        root_s3::PutOptions {
+            content_type: self.content_type,
+            cache_control: self.cache_control,
+            content_disposition: self.content_disposition,
+            content_encoding: self.content_encoding,
+            storage_class: self.storage_class.as_deref().map(StorageClass::from),
+            metadata: self.metadata,
+            tagging: self.tagging,
+            headers: self.headers,
+        }
+    }
+
+    fn into_copy_options(self) -> root_s3::CopyOptions {
+        root_s3::CopyOptions {
+            content_type: self.content_type,
+            cache_control: self.cache_control,
+            content_disposition: self.content_disposition,
+            content_encoding: self.content_encoding,
+            storage_class: self.storage_class.as_deref().map(StorageClass::from),
+            metadata: self.metadata,
+            tagging: self.tagging,
+            headers: self.headers,
+            ..Default::default()
+        }
+    }
+}
+
+/// A local path or an object, written as `s3://bucket/key`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Local(std::path::PathBuf),
+    S3 { bucket: String, key: String },
+}
+
+fn parse_location(s: &str) -> Result<Location, String> {
+    let Some(rest) = s.strip_prefix("s3://") else {
+        return Ok(Location::Local(s.into()));
+    };
+
+    let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+    if bucket.is_empty() {
+        return Err(format!("missing bucket in {s:?}"));
+    }
+
+    Ok(Location::S3 {
+        bucket: bucket.to_string(),
+        key: key.to_string(),
+    })
+}
+
+// Key to copy `source` to, adding its file name when `key` is a "directory"
+fn key_for(key: &str, source: &std::path::Path) -> String {
+    if !key.is_empty() && !key.ends_with('/') {
+        return key.to_string();
+    }
+
+    let name = source.file_name().unwrap_or_default().to_string_lossy();
+    format!("{key}{name}")
+}
+
+// Path to download `key` to, adding its last segment when `path` is a directory
+fn path_for(path: std::path::PathBuf, key: &str) -> std::path::PathBuf {
+    if path.is_dir() {
+        path.join(key.rsplit('/').next().unwrap_or(key))
+    } else {
+        path
+    }
+}
+
+// Size like "512", "10K" or "1.5G", with 1024-based suffixes
+fn parse_size(s: &str) -> Result<i64, String> {
+    let s = s.trim();
+    let (number, unit) = s
+        .find(|c: char| c.is_ascii_alphabetic())
+        .map_or((s, ""), |i| s.split_at(i));
+
+    let multiplier: i64 = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" | "KI" => 1 << 10,
+        "M" | "MI" => 1 << 20,
+        "G" | "GI" => 1 << 30,
+        "T" | "TI" => 1 << 40,
+        _ => return Err(format!("unknown size unit in {s:?}, expected K, M, G or T")),
+    };
+    #[allow(clippy::cast_precision_loss)]
+    let size = number
+        .trim()
+        .parse::<f64>()
+        .map(|number| number * multiplier as f64)
+        .ok()
+        .filter(|size| (0.0..=i64::MAX as f64).contains(size))
+        .ok_or_else(|| format!("invalid size {s:?}, expected a positive number"))?;
+
+    #[allow(clippy::cast_possible_truncation)]
+    Ok(size as i64)
+}
+
+// Rate in bytes per second, a size like "10M"
+fn parse_rate(s: &str) -> Result<u64, String> {
+    match u64::try_from(parse_size(s)?) {
+        Ok(rate) if rate > 0 => Ok(rate),
+        _ => Err(format!("invalid rate {s:?}, expected a size above 0")),
+    }
+}
+
+// Date like "2024-05-01", "2024-05-01T12:00:00Z", or a time ago like "12h" or "7d"
+fn parse_date(s: &str) -> Result<DateTime, String> {
+    if let Ok(date) = DateTime::from_str(s, DateTimeFormat::DateTime) {
+        return Ok(date);
+    }
+    if let Ok(date) = DateTime::from_str(&format!("{s}T00:00:00Z"), DateTimeFormat::DateTime) {
+        return Ok(date);
+    }
+
+    let (number, seconds): (&str, u64) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], 1),
+        Some((i, 'm')) => (&s[..i], 60),
+        Some((i, 'h')) => (&s[..i], 60 * 60),
+        Some((i, 'd')) => (&s[..i], 24 * 60 * 60),
+        Some((i, 'w')) => (&s[..i], 7 * 24 * 60 * 60),
+        _ => (s, 0),
+    };
+    let ago = number
+        .parse::<u64>()
+        .ok()
+        .filter(|_| seconds > 0)
+        .ok_or_else(|| {
+            format!("expected a date like 2024-05-01 or a time ago like 7d, got {s:?}")
+        })?;
+
+    ago.checked_mul(seconds)
+        .and_then(|ago| {
+            std::time::SystemTime::now().checked_sub(std::time::Duration::from_secs(ago))
+        })
+        .map(DateTime::from)
+        .ok_or_else(|| format!("{s:?} is too long ago"))
+}
+
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got {s:?}"))?;
+
+    Ok((key.trim().to_string(), value.trim().to_string()))
+}
+
+fn parse_metadata(s: &str) -> Result<HashMap<String, String>, String> {
+    s.split(',').map(parse_key_value).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_key_value_cases() {
+        let cases = [
+            ("key=value", Some(("key", "value"))),
+            (" key = value ", Some(("key", "value"))),
+            ("key=", Some(("key", ""))),
+            ("key=a=b", Some(("key", "a=b"))),
+            ("key", None),
+            ("", None),
+        ];
+
+        for (s, expected) in cases {
+            let parsed = parse_key_value(s).ok();
+            let parsed = parsed.as_ref().map(|(k, v)| (k.as_str(), v.as_str()));
+            assert_eq!(parsed, expected, "{s:?}");
+        }
+    }
+
+    #[test]
+    fn parse_metadata_cases() {
+        let metadata = parse_metadata("env=prod, team = data,empty=").unwrap();
+        assert_eq!(
+            metadata,
+            HashMap::from([
+                ("env".to_string(), "prod".to_string()),
+                ("team".to_string(), "data".to_string()),
+                ("empty".to_string(), String::new()),
+            ])
+        );
+        // Later values win
+        assert_eq!(parse_metadata("a=1,a=2").unwrap()["a"], "2");
+
+        for s in ["", "env=prod,", "env=prod,team"] {
+            assert!(parse_metadata(s).is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn parse_location_cases() {
+        let s3 = |bucket: &str, key: &str| {
+            Some(Location::S3 {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+            })
+        };
+        let local = |path: &str| Some(Location::Local(path.into()));
+
+        let cases = [
+            ("s3://bucket", s3("bucket", "")),
+            ("s3://bucket/", s3("bucket", "")),
+            ("s3://bucket/dir/", s3("bucket", "dir/")),
+            ("s3://bucket/dir/file.txt", s3("bucket", "dir/file.txt")),
+            ("s3://bucket//double", s3("bucket", "/double")),
+            ("s3://", None),
+            ("s3:///key", None),
+            ("-", local("-")),
+            ("file.txt", local("file.txt")),
+            ("./dir/", local("./dir/")),
+            // Only the lowercase scheme is a bucket
+            ("S3://bucket/key", local("S3://bucket/key")),
+        ];
+
+        for (s, expected) in cases {
+            assert_eq!(parse_location(s).ok(), expected, "{s:?}");
+        }
+    }
+
+    #[test]
+    fn key_for_adds_the_file_name_to_directories() {
+        let source = std::path::Path::new("/tmp/data/report.csv");
+
+        assert_eq!(key_for("", source), "report.csv");
+        assert_eq!(key_for("dir/", source), "dir/report.csv");
+        assert_eq!(key_for("dir/sub/", source), "dir/sub/report.csv");
+        assert_eq!(key_for("dir/other.csv", source), "dir/other.csv");
+        assert_eq!(key_for("dir", source), "dir");
+    }
+
+    #[test]
+    fn path_for_adds_the_last_segment_in_directories() {
+        let directory =
+            std::env::temp_dir().join(format!("root-s3-path-for-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        assert_eq!(
+            path_for(directory.clone(), "dir/report.csv"),
+            directory.join("report.csv")
+        );
+        assert_eq!(
+            path_for(directory.clone(), "report.csv"),
+            directory.join("report.csv")
+        );
+        let file = directory.join("file.csv");
+        assert_eq!(path_for(file.clone(), "dir/report.csv"), file);
+        assert_eq!(
+            path_for("-".into(), "dir/report.csv"),
+            std::path::PathBuf::from("-")
+        );
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn parse_size_cases() {
+        let cases = [
+            ("512", Some(512)),
+            (" 512 ", Some(512)),
+            ("0", Some(0)),
+            ("10K", Some(10 << 10)),
+            ("10kb", Some(10 << 10)),
+            ("1.5G", Some(3 << 29)),
+            ("2MiB", Some(2 << 20)),
+            ("1T", Some(1 << 40)),
+            ("-1", None),
+            ("-5K", None),
+            ("NaN", None),
+            ("inf", None),
+            ("", None),
+            ("K", None),
+            ("10X", None),
+            ("1.5.5", None),
+            ("99999999999T", None),
+        ];
+
+        for (s, expected) in cases {
+            assert_eq!(parse_size(s).ok(), expected, "{s:?}");
+        }
+    }
+
+    #[test]
+    fn parse_date_cases() {
+        assert_eq!(parse_date("2024-05-01").unwrap().secs(), 1_714_521_600);
+        assert_eq!(
+            parse_date("2024-05-01T12:00:00Z").unwrap().secs(),
+            1_714_521_600 + 12 * 60 * 60
+        );
+
+        let now = DateTime::from(std::time::SystemTime::now()).secs();
+        let ago = |s| now - parse_date(s).unwrap().secs();
+        assert!((30..=32).contains(&ago("30s")));
+        assert!((7 * 24 * 60 * 60..=7 * 24 * 60 * 60 + 2).contains(&ago("7d")));
+        assert!((2 * 7 * 24 * 60 * 60..=2 * 7 * 24 * 60 * 60 + 2).contains(&ago("2w")));
+
+        for s in [
+            "",
+            "d",
+            "7",
+            "7y",
+            "-1d",
+            "1.5d",
+            "7é",
+            "é",
+            "2024-13-01",
+            // Overflows the seconds
+            "99999999999999999d",
+            // Before the earliest time the system can represent
+            "10000000000000000000s",
+        ] {
+            assert!(parse_date(s).is_err(), "{s:?}");
+        }
+    }
+}
@@ -0,0 +1,608 @@
+use crate::{
+    error::CliError,
+    output::{print_one, OutputFormat, Printer, Render},
+    S3Cli,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Profile used when neither --profile nor `default_profile` is set
+const DEFAULT_PROFILE: &str = "default";
+
+/// The config file, `~/.config/root-s3/config.toml` unless `ROOT_S3_CONFIG` points elsewhere
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ConfigFile {
+    /// Profile used without --profile
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Connection settings and credentials, the flags and environment variables override them
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub addressing_style: Option<String>,
+    /// Bytes per second, like "10M"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit_rate: Option<String>,
+    /// API key stored in the file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// Environment variable to read the API key from, to keep it out of the file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_key: Option<String>,
+    /// Environment variable to read the secret key from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_key_env: Option<String>,
+}
+
+/// Settings of a profile that can be changed with `config set`
+const KEYS: &[&str] = &[
+    "url",
+    "org_id",
+    "project_id",
+    "addressing_style",
+    "limit_rate",
+    "api_key",
+    "api_key_env",
+    "access_key",
+    "secret_key",
+    "secret_key_env",
+];
+
+impl Profile {
+    fn set(&mut self, key: &str, value: Option<String>) -> Result<(), CliError> {
+        let parse_id = |value: Option<String>| {
+            value
+                .map(|value| value.parse::<i32>())
+                .transpose()
+                .map_err(|_| {
+                    CliError::usage("updating config", format!("{key} has to be a number"))
+                })
+        };
+
+        match key {
+            "url" => self.url = value,
+            "org_id" => self.org_id = parse_id(value)?,
+            "project_id" => self.project_id = parse_id(value)?,
+            "addressing_style" => {
+                if let Some(style) = &value {
+                    style
+                        .parse::<root_s3::AddressingStyle>()
+                        .map_err(|e| CliError::from_root("updating config", &e))?;
+                }
+                self.addressing_style = value;
+            }
+            "limit_rate" => {
+                if let Some(rate) = &value {
+                    crate::parse_rate(rate).map_err(|e| CliError::usage("updating config", e))?;
+                }
+                self.limit_rate = value;
+            }
+            "api_key" => self.api_key = value,
+            "api_key_env" => self.api_key_env = value,
+            "access_key" => self.access_key = value,
+            "secret_key" => self.secret_key = value,
+            "secret_key_env" => self.secret_key_env = value,
+            _ => {
+                return Err(CliError::usage(
+                    "updating config",
+                    format!(
+                        "unknown setting {key:?}, expected one of {}",
+                        KEYS.join(", ")
+                    ),
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    // Fill in the arguments that were not given as flags or environment variables
+    fn apply(&self, args: &mut S3Cli) -> Result<(), CliError> {
+        args.url = args.url.take().or_else(|| self.url.clone());
+        args.org_id = args.org_id.or(self.org_id);
+        args.project_id = args.project_id.or(self.project_id);
+
+        if args.addressing_style.is_none() {
+            args.addressing_style = self
+                .addressing_style
+                .as_deref()
+                .map(str::parse)
+                .transpose()
+                .map_err(|e| CliError::from_root("reading config", &e))?;
+        }
+        if args.limit_rate.is_none() {
+            args.limit_rate = self
+                .limit_rate
+                .as_deref()
+                .map(crate::parse_rate)
+                .transpose()
+                .map_err(|e| CliError::usage("reading config", e))?;
+        }
+
+        // Credentials come as a pair, don't mix a flag with a profile
+        if args.api_key.is_none() && args.access_key.is_none() {
+            args.api_key = self.api_key();
+        }
+        if args.api_key.is_none() && args.access_key.is_none() {
+            args.access_key.clone_from(&self.access_key);
+            args.secret_key = args.secret_key.take().or_else(|| self.secret_key());
+        }
+
+        Ok(())
+    }
+
+    /// API key of the file, or from the environment variable named in it
+    pub fn api_key(&self) -> Option<String> {
+        self.api_key
+            .clone()
+            .or_else(|| env(self.api_key_env.as_ref()))
+    }
+
+    /// Secret key of the file, or from the environment variable named in it
+    pub fn secret_key(&self) -> Option<String> {
+        self.secret_key
+            .clone()
+            .or_else(|| env(self.secret_key_env.as_ref()))
+    }
+}
+
+/// Reads a profile of the config file, e.g. for the destination of a mirror
+pub fn profile(name: &str) -> Result<Profile, CliError> {
+    let path = config_path();
+    let config = if path.exists() {
+        load(&path)?
+    } else {
+        ConfigFile::default()
+    };
+
+    config.profiles.get(name).cloned().ok_or_else(|| {
+        CliError::usage(
+            "reading config",
+            format!("profile {name:?} not found in {}", path.display()),
+        )
+    })
+}
+
+/// Applies the environment variables and the selected profile to the arguments that were not
+/// given as flags
+pub fn apply(args: &mut S3Cli) -> Result<(), CliError> {
+    // `API_KEY` is what the README has always told users to export
+    if args.api_key.is_none() && args.access_key.is_none() {
+        args.api_key = std::env::var("API_KEY").ok().filter(|key| !key.is_empty());
+    }
+
+    let path = config_path();
+    if !path.exists() {
+        return match &args.profile {
+            Some(profile) => Err(CliError::usage(
+                "reading config",
+                format!(
+                    "profile {profile:?} not found, {} does not exist",
+                    path.display()
+                ),
+            )),
+            None => Ok(()),
+        };
+    }
+
+    let config = load(&path)?;
+    let name = args
+        .profile
+        .clone()
+        .or_else(|| config.default_profile.clone());
+
+    match name {
+        Some(name) => {
+            let profile = config.profiles.get(&name).ok_or_else(|| {
+                CliError::usage(
+                    "reading config",
+                    format!("profile {name:?} not found in {}", path.display()),
+                )
+            })?;
+            profile.apply(args)
+        }
+        None => match config.profiles.get(DEFAULT_PROFILE) {
+            Some(profile) => profile.apply(args),
+            None => Ok(()),
+        },
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the path of the config file
+    Path,
+    /// List the profiles
+    List,
+    /// Show the settings of a profile, secrets are masked
+    Show {
+        /// Profile to show, the default one if not given
+        profile: Option<String>,
+    },
+    /// Change a setting of a profile, creating the profile if needed
+    Set {
+        profile: String,
+        #[arg(value_parser = clap::builder::PossibleValuesParser::new(KEYS))]
+        key: String,
+        value: String,
+    },
+    /// Remove a setting from a profile
+    Unset {
+        profile: String,
+        #[arg(value_parser = clap::builder::PossibleValuesParser::new(KEYS))]
+        key: String,
+    },
+    /// Remove a profile
+    Remove { profile: String },
+    /// Use a profile when --profile is not given
+    Use { profile: String },
+}
+
+/// A profile as printed by `config list` and `config show`
+#[derive(Serialize, Debug)]
+struct ProfileEntry {
+    name: String,
+    default: bool,
+    #[serde(flatten)]
+    profile: Profile,
+}
+
+impl ProfileEntry {
+    fn new(name: &str, profile: &Profile, default: bool) -> Self {
+        let mask = |secret: &Option<String>| secret.as_ref().map(|_| "********".to_string());
+
+        Self {
+            name: name.to_string(),
+            default,
+            profile: Profile {
+                api_key: mask(&profile.api_key),
+                secret_key: mask(&profile.secret_key),
+                ..profile.clone()
+            },
+        }
+    }
+}
+
+impl Render for ProfileEntry {
+    const COLUMNS: &'static [&'static str] = &["NAME", "DEFAULT", "URL", "ORG", "PROJECT", "AUTH"];
+
+    fn cells(&self) -> Vec<String> {
+        let id = |id: Option<i32>| id.map(|id| id.to_string()).unwrap_or_default();
+
+        vec![
+            self.name.clone(),
+            if self.default { "*" } else { "" }.to_string(),
+            self.profile.url.clone().unwrap_or_default(),
+            id(self.profile.org_id),
+            id(self.profile.project_id),
+            self.auth().to_string(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        let marker = if self.default { " (default)" } else { "" };
+        let settings = toml::to_string(&self.profile).unwrap_or_default();
+
+        format!("[{}]{marker}\n{}", self.name, settings.trim_end())
+    }
+}
+
+impl ProfileEntry {
+    // Where the credentials of the profile come from
+    fn auth(&self) -> &'static str {
+        match &self.profile {
+            Profile {
+                api_key: Some(_), ..
+            } => "api key",
+            Profile {
+                api_key_env: Some(_),
+                ..
+            } => "api key from env",
+            Profile {
+                access_key: Some(_),
+                ..
+            } => "access key",
+            _ => "",
+        }
+    }
+}
+
+pub fn run(command: &ConfigCommand, format: OutputFormat) -> Result<(), CliError> {
+    let path = config_path();
+    let mut config = if path.exists() {
+        load(&path)?
+    } else {
+        ConfigFile::default()
+    };
+    let default_name = config
+        .default_profile
+        .clone()
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+
+    match command {
+        ConfigCommand::Path => {
+            println!("{}", path.display());
+            return Ok(());
+        }
+        ConfigCommand::List => {
+            let mut printer = Printer::new(format);
+            for (name, profile) in &config.profiles {
+                printer.item(&ProfileEntry::new(name, profile, *name == default_name));
+            }
+            printer.finish();
+            return Ok(());
+        }
+        ConfigCommand::Show { profile } => {
+            let name = profile.clone().unwrap_or(default_name.clone());
+            let profile = config.profiles.get(&name).ok_or_else(|| {
+                CliError::usage("reading config", format!("profile {name:?} not found"))
+            })?;
+            print_one(
+                format,
+                &ProfileEntry::new(&name, profile, name == default_name),
+            );
+            return Ok(());
+        }
+        ConfigCommand::Set {
+            profile,
+            key,
+            value,
+        } => {
+            config
+                .profiles
+                .entry(profile.clone())
+                .or_default()
+                .set(key, Some(value.clone()))?;
+        }
+        ConfigCommand::Unset { profile, key } => {
+            let Some(profile) = config.profiles.get_mut(profile) else {
+                return Err(CliError::usage(
+                    "updating config",
+                    format!("profile {profile:?} not found"),
+                ));
+            };
+            profile.set(key, None)?;
+        }
+        ConfigCommand::Remove { profile } => {
+            if config.profiles.remove(profile).is_none() {
+                return Err(CliError::usage(
+                    "updating config",
+                    format!("profile {profile:?} not found"),
+                ));
+            }
+            if config.default_profile.as_ref() == Some(profile) {
+                config.default_profile = None;
+            }
+        }
+        ConfigCommand::Use { profile } => {
+            if !config.profiles.contains_key(profile) {
+                return Err(CliError::usage(
+                    "updating config",
+                    format!("profile {profile:?} not found"),
+                ));
+            }
+            config.default_profile = Some(profile.clone());
+        }
+    }
+
+    save(&path, &config)
+}
+
+fn config_path() -> PathBuf {
+    if let Some(path) = std::env::var_os("ROOT_S3_CONFIG") {
+        return path.into();
+    }
+
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .unwrap_or_default();
+
+    config_dir.join("root-s3").join("config.toml")
+}
+
+/// Directory of the checkpoints of unfinished uploads, `ROOT_S3_CHECKPOINT_DIR` or
+/// `$XDG_STATE_HOME/root-s3/uploads`
+pub fn checkpoint_dir() -> PathBuf {
+    if let Some(path) = std::env::var_os("ROOT_S3_CHECKPOINT_DIR") {
+        return path.into();
+    }
+
+    let state_dir = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))
+        .unwrap_or_default();
+
+    state_dir.join("root-s3").join("uploads")
+}
+
+fn load(path: &Path) -> Result<ConfigFile, CliError> {
+    let content = std::fs::read_to_string(path).map_err(|e| CliError::file(path.display(), e))?;
+
+    toml::from_str(&content).map_err(|e| {
+        CliError::usage(
+            "reading config",
+            format!("{}: {}", path.display(), e.message()),
+        )
+    })
+}
+
+fn save(path: &Path, config: &ConfigFile) -> Result<(), CliError> {
+    let content = toml::to_string_pretty(config)
+        .map_err(|e| CliError::usage("updating config", e.to_string()))?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| CliError::file(dir.display(), e))?;
+    }
+
+    // The file can hold API keys, so it is never readable by others, not even before it is
+    // complete: it is written to a new file only the user can read, then renamed into place
+    let temp = path.with_extension("toml.tmp");
+    let _ = std::fs::remove_file(&temp);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(&temp)
+        .and_then(|mut file| {
+            std::io::Write::write_all(&mut file, content.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&temp, path))
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&temp);
+            CliError::file(path.display(), e)
+        })
+}
+
+fn env(var: Option<&String>) -> Option<String> {
+    var.and_then(|var| std::env::var(var).ok())
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn parse(flags: &[&str]) -> S3Cli {
+        S3Cli::try_parse_from(["s3-cli"].iter().chain(flags).chain(&["list-buckets"])).unwrap()
+    }
+
+    #[test]
+    fn save_writes_a_file_only_the_user_can_read() {
+        let directory =
+            std::env::temp_dir().join(format!("root-s3-config-save-{}", std::process::id()));
+        let path = directory.join("config.toml");
+        let mut config = ConfigFile::default();
+        config.profiles.insert(
+            "default".to_owned(),
+            Profile {
+                api_key: Some("secret".to_owned()),
+                ..Profile::default()
+            },
+        );
+
+        save(&path, &config).unwrap();
+        // Saving again replaces the file
+        config.default_profile = Some("default".to_owned());
+        save(&path, &config).unwrap();
+
+        let saved = load(&path).unwrap();
+        assert_eq!(saved.default_profile.as_deref(), Some("default"));
+        assert_eq!(saved.profiles["default"].api_key.as_deref(), Some("secret"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert!(!path.with_extension("toml.tmp").exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    // The only test that changes the environment, which is shared by all tests
+    #[test]
+    fn apply_takes_flags_then_env_then_profile_then_defaults() {
+        let directory =
+            std::env::temp_dir().join(format!("root-s3-config-apply-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+[profiles.default]
+url = "http://default"
+org_id = 1
+project_id = 2
+addressing_style = "virtual-hosted"
+limit_rate = "1K"
+api_key = "profile-key"
+
+[profiles.keys]
+access_key = "profile-access"
+secret_key = "profile-secret"
+"#,
+        )
+        .unwrap();
+        std::env::set_var("ROOT_S3_CONFIG", &path);
+
+        // The profile fills in what is not given
+        let mut args = parse(&[]);
+        apply(&mut args).unwrap();
+        assert_eq!(args.url.as_deref(), Some("http://default"));
+        assert_eq!((args.org_id, args.project_id), (Some(1), Some(2)));
+        assert_eq!(
+            args.addressing_style,
+            Some(root_s3::AddressingStyle::VirtualHosted)
+        );
+        assert_eq!(args.limit_rate, Some(1024));
+        assert_eq!(args.api_key.as_deref(), Some("profile-key"));
+
+        // Environment variables take precedence over the profile
+        std::env::set_var("ROOT_S3_ORG_ID", "3");
+        std::env::set_var("API_KEY", "env-key");
+        let mut args = parse(&[]);
+        apply(&mut args).unwrap();
+        assert_eq!((args.org_id, args.project_id), (Some(3), Some(2)));
+        assert_eq!(args.api_key.as_deref(), Some("env-key"));
+
+        // Flags take precedence over both
+        let mut args = parse(&[
+            "--org-id",
+            "4",
+            "--api-key",
+            "flag-key",
+            "-u",
+            "http://flag",
+        ]);
+        apply(&mut args).unwrap();
+        assert_eq!(args.url.as_deref(), Some("http://flag"));
+        assert_eq!((args.org_id, args.project_id), (Some(4), Some(2)));
+        assert_eq!(args.api_key.as_deref(), Some("flag-key"));
+        std::env::remove_var("ROOT_S3_ORG_ID");
+        std::env::remove_var("API_KEY");
+
+        // Credentials are taken as a pair, an access key given as a flag keeps the API key of
+        // the profile out
+        let mut args = parse(&["--access-key", "flag-access"]);
+        apply(&mut args).unwrap();
+        assert_eq!(args.api_key, None);
+        assert_eq!(args.access_key.as_deref(), Some("flag-access"));
+        assert_eq!(args.secret_key, None);
+
+        // A profile without a setting leaves the default of the client
+        let mut args = parse(&["--profile", "keys"]);
+        apply(&mut args).unwrap();
+        assert_eq!(
+            (args.url, args.org_id, args.addressing_style),
+            (None, None, None)
+        );
+        assert_eq!(args.access_key.as_deref(), Some("profile-access"));
+        assert_eq!(args.secret_key.as_deref(), Some("profile-secret"));
+
+        std::env::remove_var("ROOT_S3_CONFIG");
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
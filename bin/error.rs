@@ -0,0 +1,150 @@
+use crate::output::ErrorReport;
+use std::process::ExitCode;
+
+/// Exit codes of the cli, also listed in the README
+pub const EXIT_FAILURE: u8 = 1;
+/// Invalid arguments, the same code clap uses
+pub const EXIT_USAGE: u8 = 2;
+/// The credentials or API key were rejected, or give no access
+pub const EXIT_AUTH: u8 = 3;
+/// The bucket, object, organisation, project or local file does not exist
+pub const EXIT_NOT_FOUND: u8 = 4;
+/// The server could not be reached or did not answer in time
+pub const EXIT_NETWORK: u8 = 5;
+/// Some items of a command on many objects failed, the others succeeded
+pub const EXIT_PARTIAL: u8 = 6;
+
+/// A failed command, with the exit code to return
+#[derive(Debug)]
+pub struct CliError {
+    pub exit_code: u8,
+    pub report: ErrorReport,
+}
+
+impl CliError {
+    pub fn usage(action: &str, message: impl Into<String>) -> Self {
+        Self::new(EXIT_USAGE, ErrorReport::new(action, message))
+    }
+
+    pub fn partial(action: &str, message: impl Into<String>) -> Self {
+        Self::new(EXIT_PARTIAL, ErrorReport::new(action, message))
+    }
+
+    pub fn new(exit_code: u8, mut report: ErrorReport) -> Self {
+        report.kind = kind(exit_code);
+        Self { exit_code, report }
+    }
+
+    /// An error of the library, with a hint on how to fix it when the Root gateway rejected
+    /// the request
+    pub fn from_root(action: &str, e: &root_s3::Error) -> Self {
+        let hint = match e {
+            root_s3::Error::InvalidApiKey => {
+                Some("check the key passed with --api-key and that it has not been revoked")
+            }
+            root_s3::Error::UnknownOrganisation(_) => {
+                Some("check the organisation passed with --org-id")
+            }
+            root_s3::Error::UnknownProject(_) => Some(
+                "check the project passed with --project-id and that it belongs to the organisation",
+            ),
+            root_s3::Error::ForbiddenProject(_) => {
+                Some("the API key has no access to this project, use a key created for it")
+            }
+            root_s3::Error::Connection(_) => Some("check the url passed with --url"),
+            _ => None,
+        };
+
+        let report = ErrorReport {
+            hint,
+            // The display of SDK errors leaves out the reason, which is in the debug output
+            details: hint.is_none().then(|| format!("{e:?}")),
+            ..ErrorReport::new(action, e.to_string())
+        };
+
+        Self::new(exit_code(e), report)
+    }
+
+    /// A local file that could not be read or written
+    pub fn file(path: impl std::fmt::Display, e: std::io::Error) -> Self {
+        let mut error = Self::from(e);
+        error.report.message = format!("{path}: {}", error.report.message);
+        error
+    }
+
+    pub fn exit(&self) -> ExitCode {
+        ExitCode::from(self.exit_code)
+    }
+}
+
+impl From<std::io::Error> for CliError {
+    fn from(e: std::io::Error) -> Self {
+        let exit_code = match e.kind() {
+            std::io::ErrorKind::NotFound => EXIT_NOT_FOUND,
+            _ => EXIT_FAILURE,
+        };
+
+        Self::new(
+            exit_code,
+            ErrorReport::new("accessing local file", e.to_string()),
+        )
+    }
+}
+
+impl From<serde_json::Error> for CliError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::new(
+            EXIT_FAILURE,
+            ErrorReport::new("reading report", e.to_string()),
+        )
+    }
+}
+
+/// Maps a library error to a failure with `action` as context, for `map_err`
+pub fn failed(action: &'static str) -> impl Fn(root_s3::Error) -> CliError {
+    move |e| CliError::from_root(action, &e)
+}
+
+fn exit_code(e: &root_s3::Error) -> u8 {
+    match e {
+        root_s3::Error::InvalidApiKey | root_s3::Error::ForbiddenProject(_) => EXIT_AUTH,
+        root_s3::Error::UnknownOrganisation(_) | root_s3::Error::UnknownProject(_) => {
+            EXIT_NOT_FOUND
+        }
+        root_s3::Error::Connection(_) => EXIT_NETWORK,
+        root_s3::Error::LocalFile(_, e) if e.kind() == std::io::ErrorKind::NotFound => {
+            EXIT_NOT_FOUND
+        }
+        root_s3::Error::InvalidUrl
+        | root_s3::Error::InvalidAddressingStyle(_)
+        | root_s3::Error::InvalidPattern(_)
+        | root_s3::Error::InvalidSortOrder(_)
+        | root_s3::Error::InvalidTagging(_)
+        | root_s3::Error::InvalidMove(_)
+        | root_s3::Error::InvalidMirror(_) => EXIT_USAGE,
+        _ => match e.code() {
+            Some(
+                "AccessDenied"
+                | "Forbidden"
+                | "InvalidAccessKeyId"
+                | "SignatureDoesNotMatch"
+                | "ExpiredToken"
+                | "InvalidToken",
+            ) => EXIT_AUTH,
+            Some("NoSuchBucket" | "NoSuchKey" | "NotFound" | "NoSuchUpload") => EXIT_NOT_FOUND,
+            _ => EXIT_FAILURE,
+        },
+    }
+}
+
+// Name of an exit code in structured errors
+fn kind(exit_code: u8) -> &'static str {
+    match exit_code {
+        EXIT_USAGE => "usage",
+        EXIT_AUTH => "auth",
+        EXIT_NOT_FOUND => "not_found",
+        EXIT_NETWORK => "network",
+        EXIT_PARTIAL => "partial_failure",
+        _ => "failure",
+    }
+}
@@ -0,0 +1,753 @@
+use aws_sdk_s3::{
+    operation::head_object::HeadObjectOutput,
+    primitives::{DateTime, DateTimeFormat},
+    types::{Bucket, Object},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, io::Write, marker::PhantomData};
+
+/// How results are printed
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// A JSON document, lists are printed as one array
+    Json,
+    /// One JSON document per line, one line per item of a list
+    Ndjson,
+    /// A YAML document, lists are printed as one sequence
+    Yaml,
+    /// Aligned columns with a header
+    Table,
+    /// Human readable lines
+    #[default]
+    Plain,
+}
+
+impl OutputFormat {
+    /// Whether errors are printed as JSON for scripts
+    pub fn is_structured(self) -> bool {
+        matches!(self, Self::Json | Self::Ndjson | Self::Yaml)
+    }
+}
+
+/// A result that can be printed in every [`OutputFormat`]
+pub trait Render: Serialize {
+    const COLUMNS: &'static [&'static str];
+
+    /// Values of [`Render::COLUMNS`] in a table
+    fn cells(&self) -> Vec<String>;
+
+    /// Line printed by the plain format
+    fn plain(&self) -> String;
+}
+
+/// Prints the results of a command, streaming them when the format allows it
+pub struct Printer<T> {
+    format: OutputFormat,
+    // Items kept until `finish` for the formats that need all of them
+    rows: Vec<Vec<String>>,
+    values: Vec<serde_json::Value>,
+    item: PhantomData<T>,
+}
+
+impl<T: Render> Printer<T> {
+    pub fn new(format: OutputFormat) -> Self {
+        Self {
+            format,
+            rows: Vec::new(),
+            values: Vec::new(),
+            item: PhantomData,
+        }
+    }
+
+    /// Prints one item of a list
+    pub fn item(&mut self, item: &T) {
+        match self.format {
+            OutputFormat::Json => self.values.push(to_value(item)),
+            OutputFormat::Ndjson => emit(&to_value(item).to_string()),
+            OutputFormat::Yaml => emit(to_yaml(&[item]).trim_end()),
+            OutputFormat::Table => self.rows.push(item.cells()),
+            OutputFormat::Plain => emit(&item.plain()),
+        }
+    }
+
+    /// Prints the items kept for the json and table formats
+    pub fn finish(self) {
+        match self.format {
+            OutputFormat::Json => emit(&pretty(&self.values)),
+            OutputFormat::Table => print_table(T::COLUMNS, &self.rows),
+            OutputFormat::Ndjson | OutputFormat::Yaml | OutputFormat::Plain => {}
+        }
+    }
+}
+
+/// Prints the single result of a command
+pub fn print_one<T: Render>(format: OutputFormat, item: &T) {
+    match format {
+        OutputFormat::Json => emit(&pretty(item)),
+        OutputFormat::Ndjson => emit(&to_value(item).to_string()),
+        OutputFormat::Yaml => emit(to_yaml(item).trim_end()),
+        OutputFormat::Table => print_table(T::COLUMNS, &[item.cells()]),
+        OutputFormat::Plain => emit(&item.plain()),
+    }
+}
+
+/// An error, printed on stderr
+#[derive(Serialize, Debug)]
+pub struct ErrorReport {
+    /// Kind of failure, matching the exit code: `usage`, `auth`, `not_found`, `network`,
+    /// `partial_failure` or `failure`
+    pub kind: &'static str,
+    /// What the command was doing, e.g. "listing objects"
+    pub action: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+}
+
+impl ErrorReport {
+    pub fn new(action: &str, message: impl Into<String>) -> Self {
+        Self {
+            kind: "failure",
+            action: action.to_string(),
+            message: message.into(),
+            hint: None,
+            details: None,
+        }
+    }
+
+    /// Prints the error as `{"error": {...}}` for the structured formats and as text otherwise
+    pub fn print(&self, format: OutputFormat) {
+        if format.is_structured() {
+            eprintln!("{}", serde_json::json!({ "error": self }));
+            return;
+        }
+
+        eprintln!("Error {}: {}", self.action, self.message);
+        if let Some(hint) = self.hint {
+            eprintln!("Hint: {hint}");
+        }
+        if let Some(details) = &self.details {
+            eprintln!("Details: {details}");
+        }
+    }
+}
+
+/// Result of a command acting on a bucket or an object
+#[derive(Serialize, Debug)]
+pub struct ActionResult {
+    /// What was done, e.g. "created" or "uploaded"
+    pub action: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Bucket, `s3://bucket/key` url or local path that was acted on
+    pub target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub e_tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<i64>,
+}
+
+impl ActionResult {
+    pub fn new(action: &'static str, target: impl Into<String>) -> Self {
+        Self {
+            action,
+            source: None,
+            target: target.into(),
+            e_tag: None,
+            size: None,
+        }
+    }
+
+    #[must_use]
+    pub fn source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    #[must_use]
+    pub fn e_tag(mut self, e_tag: Option<String>) -> Self {
+        self.e_tag = e_tag;
+        self
+    }
+
+    #[must_use]
+    pub fn size(mut self, size: Option<i64>) -> Self {
+        self.size = size;
+        self
+    }
+}
+
+impl Render for ActionResult {
+    const COLUMNS: &'static [&'static str] = &["ACTION", "SOURCE", "TARGET", "ETAG", "SIZE"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.action.to_string(),
+            self.source.clone().unwrap_or_default(),
+            self.target.clone(),
+            self.e_tag.clone().unwrap_or_default(),
+            self.size.map(|size| size.to_string()).unwrap_or_default(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        let line = match &self.source {
+            Some(source) => format!("{} {source} to {}", capitalize(self.action), self.target),
+            None => format!("{} {}", capitalize(self.action), self.target),
+        };
+
+        match self.size {
+            Some(size) => format!("{line}, {size} bytes"),
+            None => line,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct BucketEntry {
+    pub name: String,
+    pub created_at: Option<String>,
+}
+
+impl From<&Bucket> for BucketEntry {
+    fn from(bucket: &Bucket) -> Self {
+        Self {
+            name: bucket.name().unwrap_or_default().to_string(),
+            created_at: bucket.creation_date().and_then(format_date),
+        }
+    }
+}
+
+impl Render for BucketEntry {
+    const COLUMNS: &'static [&'static str] = &["NAME", "CREATED"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.created_at.clone().unwrap_or_default(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        format!(
+            "{:<20} {}",
+            self.created_at.as_deref().unwrap_or_default(),
+            self.name
+        )
+    }
+}
+
+/// An object or, in a listing with a delimiter, a common prefix
+#[derive(Serialize, Debug)]
+pub struct ObjectEntry {
+    /// "object" or "prefix"
+    pub kind: &'static str,
+    pub key: String,
+    /// Key relative to the listed prefix, as shown by the plain and table formats
+    #[serde(skip)]
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub e_tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_class: Option<String>,
+}
+
+impl ObjectEntry {
+    /// An object, shown by its key without `prefix`
+    pub fn object(object: &Object, prefix: &str) -> Self {
+        let key = object.key().unwrap_or_default();
+
+        Self {
+            kind: "object",
+            key: key.to_string(),
+            name: key.strip_prefix(prefix).unwrap_or(key).to_string(),
+            size: object.size(),
+            last_modified: object.last_modified().and_then(format_date),
+            e_tag: object.e_tag().map(str::to_string),
+            storage_class: object
+                .storage_class()
+                .map(|class| class.as_str().to_string()),
+        }
+    }
+
+    pub fn prefix(common_prefix: &str, prefix: &str) -> Self {
+        Self {
+            kind: "prefix",
+            key: common_prefix.to_string(),
+            name: common_prefix
+                .strip_prefix(prefix)
+                .unwrap_or(common_prefix)
+                .to_string(),
+            size: None,
+            last_modified: None,
+            e_tag: None,
+            storage_class: None,
+        }
+    }
+}
+
+impl Render for ObjectEntry {
+    const COLUMNS: &'static [&'static str] = &["MODIFIED", "SIZE", "CLASS", "KEY"];
+
+    fn cells(&self) -> Vec<String> {
+        if self.kind == "prefix" {
+            return vec![
+                String::new(),
+                "PRE".to_string(),
+                String::new(),
+                self.name.clone(),
+            ];
+        }
+
+        vec![
+            self.last_modified.clone().unwrap_or_default(),
+            self.size.unwrap_or_default().to_string(),
+            self.storage_class.clone().unwrap_or_default(),
+            self.name.clone(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        if self.kind == "prefix" {
+            return format!("{:>30} {}", "PRE", self.name);
+        }
+
+        format!(
+            "{:<20} {:>9} {}",
+            self.last_modified.as_deref().unwrap_or_default(),
+            self.size.unwrap_or_default(),
+            self.name
+        )
+    }
+}
+
+/// Headers and metadata of an object
+#[derive(Serialize, Debug)]
+pub struct HeadEntry {
+    pub bucket: String,
+    pub key: String,
+    pub size: Option<i64>,
+    pub last_modified: Option<String>,
+    pub e_tag: Option<String>,
+    pub content_type: Option<String>,
+    pub storage_class: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl HeadEntry {
+    pub fn new(bucket: &str, key: &str, head: &HeadObjectOutput) -> Self {
+        Self {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            size: head.content_length(),
+            last_modified: head.last_modified().and_then(format_date),
+            e_tag: head.e_tag().map(str::to_string),
+            content_type: head.content_type().map(str::to_string),
+            storage_class: head.storage_class().map(|class| class.as_str().to_string()),
+            metadata: head
+                .metadata()
+                .cloned()
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+impl Render for HeadEntry {
+    const COLUMNS: &'static [&'static str] = &[
+        "KEY",
+        "SIZE",
+        "MODIFIED",
+        "ETAG",
+        "CONTENT-TYPE",
+        "METADATA",
+    ];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.key.clone(),
+            self.size.unwrap_or_default().to_string(),
+            self.last_modified.clone().unwrap_or_default(),
+            self.e_tag.clone().unwrap_or_default(),
+            self.content_type.clone().unwrap_or_default(),
+            self.metadata_list(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        let mut lines = vec![
+            format!("key: {}", self.key),
+            format!("bucket: {}", self.bucket),
+            format!("size: {} bytes", self.size.unwrap_or_default()),
+        ];
+        let optional = [
+            ("last modified", &self.last_modified),
+            ("etag", &self.e_tag),
+            ("content type", &self.content_type),
+            ("storage class", &self.storage_class),
+        ];
+        for (name, value) in optional {
+            if let Some(value) = value {
+                lines.push(format!("{name}: {value}"));
+            }
+        }
+        if !self.metadata.is_empty() {
+            lines.push(format!("metadata: {}", self.metadata_list()));
+        }
+
+        lines.join("\n")
+    }
+}
+
+impl HeadEntry {
+    fn metadata_list(&self) -> String {
+        self.metadata
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// An object moved, or not, by `mv --recursive`
+#[derive(Serialize, Debug)]
+pub struct MoveEntry {
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    /// "moved" or "failed"
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Render for MoveEntry {
+    const COLUMNS: &'static [&'static str] = &["STATUS", "SOURCE", "DESTINATION", "ERROR"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.status.to_string(),
+            self.source.clone(),
+            self.destination.clone().unwrap_or_default(),
+            self.error.clone().unwrap_or_default(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        match (&self.destination, &self.error) {
+            (_, Some(error)) => format!("Failed to move {}: {error}", self.source),
+            (Some(destination), None) => format!("Moved {} to {destination}", self.source),
+            (None, None) => format!("Moved {}", self.source),
+        }
+    }
+}
+
+/// A file or object synced by `sync` or `mirror`
+#[derive(Serialize, Debug)]
+pub struct SyncEntry {
+    /// "uploaded", "downloaded", "copied", "deleted" or "failed"
+    pub status: &'static str,
+    pub dry_run: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    pub target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Render for SyncEntry {
+    const COLUMNS: &'static [&'static str] = &["STATUS", "SOURCE", "TARGET", "SIZE", "ERROR"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            if self.dry_run {
+                format!("{} (dry run)", self.status)
+            } else {
+                self.status.to_string()
+            },
+            self.source.clone().unwrap_or_default(),
+            self.target.clone(),
+            self.size.map(|size| size.to_string()).unwrap_or_default(),
+            self.error.clone().unwrap_or_default(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        let line = match (&self.source, &self.error) {
+            (_, Some(error)) => return format!("Failed to sync {}: {error}", self.target),
+            (Some(source), None) => {
+                format!("{} {source} to {}", capitalize(self.status), self.target)
+            }
+            (None, None) => format!("{} {}", capitalize(self.status), self.target),
+        };
+
+        if self.dry_run {
+            format!("(dry run) {line}")
+        } else {
+            line
+        }
+    }
+}
+
+/// An unfinished upload of `resume`
+#[derive(Serialize, Debug)]
+pub struct UploadEntry {
+    /// "pending" or "stale" when listed, "uploaded", "discarded" or "failed" otherwise
+    pub status: &'static str,
+    pub file: String,
+    pub target: String,
+    /// Bytes the server received
+    pub uploaded: i64,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl UploadEntry {
+    pub fn new(status: &'static str, checkpoint: &root_s3::UploadCheckpoint) -> Self {
+        let destination = &checkpoint.destination;
+        Self {
+            status,
+            file: checkpoint.path.display().to_string(),
+            target: format!("s3://{}/{}", destination.bucket, destination.key),
+            uploaded: checkpoint.uploaded(),
+            size: checkpoint.file_size,
+            started: i64::try_from(checkpoint.started)
+                .ok()
+                .and_then(|secs| format_date(&DateTime::from_secs(secs))),
+            error: None,
+        }
+    }
+
+    #[must_use]
+    pub fn error(mut self, error: impl std::fmt::Display) -> Self {
+        self.error = Some(error.to_string());
+        self
+    }
+}
+
+impl Render for UploadEntry {
+    const COLUMNS: &'static [&'static str] = &[
+        "STATUS", "FILE", "TARGET", "UPLOADED", "SIZE", "STARTED", "ERROR",
+    ];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.status.to_string(),
+            self.file.clone(),
+            self.target.clone(),
+            self.uploaded.to_string(),
+            self.size.to_string(),
+            self.started.clone().unwrap_or_default(),
+            self.error.clone().unwrap_or_default(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        match (self.status, &self.error) {
+            (_, Some(error)) => format!("Failed {} to {}: {error}", self.file, self.target),
+            ("pending" | "stale", None) => format!(
+                "{} {} to {}, {} of {} bytes uploaded since {}",
+                capitalize(self.status),
+                self.file,
+                self.target,
+                self.uploaded,
+                self.size,
+                self.started.as_deref().unwrap_or("?")
+            ),
+            (status, None) => format!("{} {} to {}", capitalize(status), self.file, self.target),
+        }
+    }
+}
+
+/// A manifest line run by `batch`, also kept in its report file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatchEntry {
+    /// Line number in the manifest, from 1
+    pub line: usize,
+    /// "copied", "tagged", "deleted", "restored" or "failed"
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub op: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// S3 error code like `NoSuchKey`, or the kind of failure when there is none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Render for BatchEntry {
+    const COLUMNS: &'static [&'static str] =
+        &["LINE", "STATUS", "OP", "SOURCE", "TARGET", "CODE", "ERROR"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.line.to_string(),
+            self.status.clone(),
+            self.op.clone().unwrap_or_default(),
+            self.source.clone().unwrap_or_default(),
+            self.target.clone().unwrap_or_default(),
+            self.code.clone().unwrap_or_default(),
+            self.error.clone().unwrap_or_default(),
+        ]
+    }
+
+    fn plain(&self) -> String {
+        let target = self.target.as_deref().unwrap_or_default();
+        match (&self.op, &self.source, &self.error) {
+            (Some(op), _, Some(error)) => {
+                format!("Line {}: failed to {op} {target}: {error}", self.line)
+            }
+            (None, _, error) => format!(
+                "Line {}: {}",
+                self.line,
+                error.as_deref().unwrap_or_default()
+            ),
+            (Some(_), Some(source), None) => format!(
+                "Line {}: {} {source} to {target}",
+                self.line,
+                capitalize(&self.status)
+            ),
+            (Some(_), None, None) => {
+                format!("Line {}: {} {target}", self.line, capitalize(&self.status))
+            }
+        }
+    }
+}
+
+// Print a line on stdout, stopping quietly when it was closed, e.g. by `| head`
+fn emit(line: &str) {
+    if let Err(e) = writeln!(std::io::stdout().lock(), "{line}") {
+        if e.kind() == std::io::ErrorKind::BrokenPipe {
+            std::process::exit(0);
+        }
+    }
+}
+
+pub fn format_date(date: &DateTime) -> Option<String> {
+    date.fmt(DateTimeFormat::DateTime).ok()
+}
+
+fn to_value<T: Serialize + ?Sized>(item: &T) -> serde_json::Value {
+    serde_json::to_value(item).unwrap_or_else(|e| serde_json::json!({ "error": e.to_string() }))
+}
+
+fn pretty<T: Serialize + ?Sized>(item: &T) -> String {
+    serde_json::to_string_pretty(item).unwrap_or_else(|e| e.to_string())
+}
+
+fn to_yaml<T: Serialize + ?Sized>(item: &T) -> String {
+    serde_yaml_ng::to_string(item).unwrap_or_else(|e| format!("# {e}\n"))
+}
+
+fn print_table(columns: &[&str], rows: &[Vec<String>]) {
+    let mut widths = columns.iter().map(|c| c.len()).collect::<Vec<_>>();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: Vec<&str>| {
+        let padded = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>();
+        emit(padded.join("  ").trim_end());
+    };
+
+    line(columns.to_vec());
+    for row in rows {
+        line(row.iter().map(String::as_str).collect());
+    }
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Keys that YAML would read as something else when written as they are
+    const KEYS: &[&str] = &[
+        "logs/app.log",
+        "a: b",
+        "# not a comment",
+        "- not a list",
+        "[1, 2]",
+        "{a}",
+        "true",
+        "12",
+        "null",
+        "~",
+        "'quoted'",
+        "\"double\"",
+        " padded ",
+        "line\nbreak",
+        "tab\there",
+        "café/日本語/🦀",
+        "",
+    ];
+
+    // What the item reads back as from its YAML, compared as JSON
+    fn round_trip<T: Serialize>(item: &T) -> serde_json::Value {
+        let yaml = to_yaml(item);
+        serde_yaml_ng::from_str(&yaml).unwrap_or_else(|e| panic!("{e}: {yaml}"))
+    }
+
+    #[test]
+    fn object_entries_read_back_from_yaml() {
+        let objects = KEYS
+            .iter()
+            .map(|key| {
+                let object = Object::builder()
+                    .key(*key)
+                    .size(12)
+                    .last_modified(DateTime::from_secs(1_714_521_600))
+                    .e_tag("\"764efa883dda1e11db47671c4a3bbd9e\"")
+                    .build();
+                ObjectEntry::object(&object, "")
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(round_trip(&objects), to_value(&objects));
+    }
+
+    #[test]
+    fn head_entries_read_back_from_yaml() {
+        for key in KEYS {
+            let head = HeadObjectOutput::builder()
+                .content_length(12)
+                .e_tag("\"764efa883dda1e11db47671c4a3bbd9e\"")
+                .content_type("text/plain; charset=utf-8")
+                .metadata(*key, *key)
+                .metadata("owner", "- me: #1")
+                .build();
+            let entry = HeadEntry::new("bucket", key, &head);
+
+            assert_eq!(round_trip(&[&entry]), to_value(&[&entry]), "{key:?}");
+        }
+    }
+}
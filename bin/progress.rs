@@ -0,0 +1,67 @@
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle};
+use root_s3::{Progress, ProgressObserver, ProgressReader};
+use std::{io::IsTerminal, sync::Arc};
+use tokio::io::AsyncRead;
+
+/// Progress bar of an upload or download, drawn on stderr
+#[derive(Clone)]
+pub struct Bar(ProgressBar);
+
+impl Bar {
+    /// A bar that is only drawn when not `quiet` and stderr is a terminal
+    pub fn new(quiet: bool) -> Self {
+        if quiet || !std::io::stderr().is_terminal() {
+            return Self(ProgressBar::hidden());
+        }
+
+        // The length is only known once the transfer starts, or never for stdin
+        // Cleared as well when the transfer fails, before the error is printed
+        let bar = ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr())
+            .with_style(
+                ProgressStyle::with_template("{spinner} {bytes} ({binary_bytes_per_sec})")
+                    .expect("valid template"),
+            )
+            .with_finish(ProgressFinish::AndClear);
+        Self(bar)
+    }
+
+    /// Observer to pass to the client, `None` when the bar is not drawn
+    pub fn observer(&self) -> Option<Arc<dyn ProgressObserver>> {
+        if self.0.is_hidden() {
+            return None;
+        }
+        Some(Arc::new(self.clone()))
+    }
+
+    /// `reader` reporting the bytes read to the bar, for stdin and stdout
+    pub fn wrap<R>(&self, reader: R, total: Option<u64>) -> Box<dyn AsyncRead + Unpin + Send>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        match self.observer() {
+            Some(observer) => Box::new(ProgressReader::new(reader, total, observer)),
+            None => Box::new(reader),
+        }
+    }
+
+    /// Remove the bar, so the result is printed on a clean line
+    pub fn finish(&self) {
+        self.0.finish_and_clear();
+    }
+}
+
+impl ProgressObserver for Bar {
+    fn progress(&self, progress: &Progress) {
+        if let (Some(total), None) = (progress.total, self.0.length()) {
+            self.0.set_length(total);
+            self.0.set_style(
+                ProgressStyle::with_template(
+                    "{bytes}/{total_bytes} [{wide_bar}] {binary_bytes_per_sec}, {eta} left",
+                )
+                .expect("valid template")
+                .progress_chars("=> "),
+            );
+        }
+        self.0.set_position(progress.transferred);
+    }
+}
@@ -0,0 +1,532 @@
+use crate::{
+    Client, CopyOptions, CopyOutcome, DownloadOutcome, Error, GetOptions, ObjectLocation,
+    ProjectContext, PutOptions, UploadOutcome,
+};
+use aws_sdk_s3::operation::head_object::HeadObjectOutput;
+use std::{collections::HashMap, future::Future, path::PathBuf, time::Duration};
+use tokio::task::{JoinError, JoinHandle, JoinSet};
+use tokio_stream::{Stream, StreamExt};
+
+/// A single operation run by [`Client::run_bulk`].
+#[derive(Debug, Clone)]
+pub enum BulkOp {
+    /// Upload a file with [`Client::upload_file`].
+    Put {
+        path: PathBuf,
+        destination: ObjectLocation,
+        options: PutOptions,
+    },
+    /// Download an object with [`Client::download_file`].
+    Get {
+        source: ObjectLocation,
+        path: PathBuf,
+        options: GetOptions,
+    },
+    /// Copy an object with [`Client::copy`].
+    Copy {
+        source: ObjectLocation,
+        destination: ObjectLocation,
+        options: CopyOptions,
+    },
+    Delete {
+        location: ObjectLocation,
+    },
+    Head {
+        location: ObjectLocation,
+    },
+    /// Replace the tags of an object with [`Client::put_object_tagging`].
+    Tag {
+        location: ObjectLocation,
+        tags: HashMap<String, String>,
+    },
+    /// Restore an archived object for `days` days with [`Client::restore_object`].
+    Restore {
+        location: ObjectLocation,
+        days: i32,
+    },
+}
+
+impl BulkOp {
+    /// Object the operation writes, or reads when it writes none.
+    #[must_use]
+    pub fn location(&self) -> &ObjectLocation {
+        match self {
+            BulkOp::Put { destination, .. } | BulkOp::Copy { destination, .. } => destination,
+            BulkOp::Get { source, .. } => source,
+            BulkOp::Delete { location }
+            | BulkOp::Head { location }
+            | BulkOp::Tag { location, .. }
+            | BulkOp::Restore { location, .. } => location,
+        }
+    }
+
+    /// Name of the operation, like `put` or `delete`.
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            BulkOp::Put { .. } => "put",
+            BulkOp::Get { .. } => "get",
+            BulkOp::Copy { .. } => "copy",
+            BulkOp::Delete { .. } => "delete",
+            BulkOp::Head { .. } => "head",
+            BulkOp::Tag { .. } => "tag",
+            BulkOp::Restore { .. } => "restore",
+        }
+    }
+}
+
+/// What a successful [`BulkOp`] returned.
+#[derive(Debug, Clone)]
+pub enum BulkOutput {
+    Put(UploadOutcome),
+    Get(DownloadOutcome),
+    Copy(CopyOutcome),
+    Delete,
+    Head(Box<HeadObjectOutput>),
+    Tag,
+    Restore,
+}
+
+/// What [`Client::run_bulk`] does once an operation failed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BulkMode {
+    /// Run all operations, whatever failed before.
+    #[default]
+    ContinueOnError,
+    /// Start no more operations after the first failure, the running ones still finish.
+    FailFast,
+}
+
+/// Options of [`Client::run_bulk`].
+#[derive(Debug, Clone)]
+pub struct BulkOptions {
+    /// Number of operations run at the same time.
+    pub concurrency: usize,
+    /// Number of times an operation is tried again after an error that may go away, see
+    /// [`Error::is_retryable`].
+    pub retries: u32,
+    /// Wait before the first retry of an operation, doubled for every next one.
+    pub retry_delay: Duration,
+    pub mode: BulkMode,
+}
+
+impl Default for BulkOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 8,
+            retries: 2,
+            retry_delay: Duration::from_millis(500),
+            mode: BulkMode::default(),
+        }
+    }
+}
+
+/// Result of one operation of [`Client::run_bulk`].
+#[derive(Debug)]
+pub struct BulkResult {
+    /// Position of the operation in the stream, from 0.
+    pub index: usize,
+    pub op: BulkOp,
+    /// Number of times the operation was sent, more than 1 when it was retried.
+    pub attempts: u32,
+    pub result: Result<BulkOutput, Error>,
+}
+
+/// Results of [`Client::run_bulk`], in the order of the operations.
+#[derive(Debug, Default)]
+pub struct BulkReport {
+    pub results: Vec<BulkResult>,
+    /// Whether [`BulkMode::FailFast`] stopped the run before all operations were started, so
+    /// operations were left out.
+    pub stopped: bool,
+}
+
+impl BulkReport {
+    pub fn succeeded(&self) -> impl Iterator<Item = &BulkResult> {
+        self.results.iter().filter(|item| item.result.is_ok())
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &BulkResult> {
+        self.results.iter().filter(|item| item.result.is_err())
+    }
+}
+
+impl Client {
+    /// Runs the operations of `ops`, at most `options.concurrency` at a time, and reports the
+    /// result of each of them.
+    ///
+    /// Operations failing with a [retryable](Error::is_retryable) error are tried again up to
+    /// `options.retries` times. A failed operation does not fail the run: it is recorded in the
+    /// report, and with [`BulkMode::FailFast`] no further operations are taken from `ops`.
+    pub async fn run_bulk<S>(&self, mut ops: S, options: &BulkOptions) -> BulkReport
+    where
+        S: Stream<Item = BulkOp> + Unpin,
+    {
+        let mut report = BulkReport::default();
+        let mut runner = Runner::new(options);
+        // Operations that are running, by index
+        let mut running = HashMap::new();
+
+        while let Some(op) = ops.next().await {
+            for finished in runner.wait_for_slot().await {
+                record_bulk(&mut report, &mut running, finished);
+            }
+            if runner.stopped() {
+                report.stopped = true;
+                break;
+            }
+
+            let label = format!("{} of {}", op.name(), op.location().key);
+            let index = runner.spawn(
+                label,
+                (self.clone(), op.clone()),
+                |(client, op)| async move { client.apply_bulk_op(&op).await },
+            );
+            running.insert(index, op);
+        }
+
+        for finished in runner.finish().await {
+            record_bulk(&mut report, &mut running, finished);
+        }
+
+        report.results.sort_by_key(|item| item.index);
+        report
+    }
+
+    /// Deletes all objects under `prefix` with [`Client::run_bulk`]. Only listing the prefix
+    /// fails the whole delete, objects that could not be deleted are in the report.
+    pub async fn delete_prefix(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        project: impl Into<ProjectContext>,
+        options: &BulkOptions,
+    ) -> Result<BulkReport, Error> {
+        let project = project.into();
+        let mut keys = self
+            .list_objects_by_key(bucket, prefix, project)
+            .await?
+            .into_keys()
+            .collect::<Vec<_>>();
+        keys.sort();
+
+        let ops = keys.into_iter().map(|key| BulkOp::Delete {
+            location: ObjectLocation::new(bucket, key, project),
+        });
+        Ok(self.run_bulk(tokio_stream::iter(ops), options).await)
+    }
+
+    async fn apply_bulk_op(&self, op: &BulkOp) -> Result<BulkOutput, Error> {
+        match op {
+            BulkOp::Put {
+                path,
+                destination,
+                options,
+            } => self
+                .upload_file(path, destination, options.clone(), None)
+                .await
+                .map(BulkOutput::Put),
+            BulkOp::Get {
+                source,
+                path,
+                options,
+            } => self
+                .download_file(source, path, options.clone(), None)
+                .await
+                .map(BulkOutput::Get),
+            BulkOp::Copy {
+                source,
+                destination,
+                options,
+            } => self
+                .copy(source, destination, options.clone())
+                .await
+                .map(BulkOutput::Copy),
+            BulkOp::Delete { location } => self
+                .delete_object_in(&location.bucket, &location.key, location.project)
+                .await
+                .map(|_| BulkOutput::Delete),
+            BulkOp::Head { location } => self
+                .head_object_in(&location.bucket, &location.key, location.project)
+                .await
+                .map(|head| BulkOutput::Head(Box::new(head))),
+            BulkOp::Tag { location, tags } => self
+                .put_object_tagging_in(&location.bucket, &location.key, tags, location.project)
+                .await
+                .map(|_| BulkOutput::Tag),
+            BulkOp::Restore { location, days } => self
+                .restore_object_in(&location.bucket, &location.key, *days, location.project)
+                .await
+                .map(|_| BulkOutput::Restore),
+        }
+    }
+}
+
+fn record_bulk(
+    report: &mut BulkReport,
+    running: &mut HashMap<usize, BulkOp>,
+    finished: Finished<BulkOutput>,
+) {
+    let Some(op) = running.remove(&finished.index) else {
+        return;
+    };
+    if let Err(e) = &finished.result {
+        log::warn!("failed to {} {}: {e}", op.name(), op.location().key);
+    }
+
+    report.results.push(BulkResult {
+        index: finished.index,
+        op,
+        attempts: finished.attempts,
+        result: finished.result,
+    });
+}
+
+// An operation run by a `Runner`
+pub(crate) struct Finished<T> {
+    // Position of the operation in the order it was started, from 0
+    pub(crate) index: usize,
+    pub(crate) attempts: u32,
+    pub(crate) result: Result<T, Error>,
+}
+
+// Runs operations at most `concurrency` at a time, trying them again after retryable errors.
+// `run_bulk`, the syncs, `mirror` and `move_prefix` all run their operations with it, so they
+// retry and stop the same way.
+pub(crate) struct Runner<T> {
+    options: BulkOptions,
+    tasks: JoinSet<Finished<T>>,
+    started: usize,
+    stopped: bool,
+}
+
+impl<T: Send + 'static> Runner<T> {
+    pub(crate) fn new(options: &BulkOptions) -> Self {
+        Self {
+            options: options.clone(),
+            tasks: JoinSet::new(),
+            started: 0,
+            stopped: false,
+        }
+    }
+
+    // Whether an operation failed with `BulkMode::FailFast`, no more should be started then
+    pub(crate) fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    // Starts `op` with a clone of `input` for every attempt, and returns its index. `label`
+    // names the operation in the log.
+    pub(crate) fn spawn<I, F, Fut>(&mut self, label: String, input: I, op: F) -> usize
+    where
+        I: Clone + Send + 'static,
+        F: Fn(I) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<T, Error>> + Send,
+    {
+        let index = self.started;
+        self.started += 1;
+
+        let (retries, retry_delay) = (self.options.retries, self.options.retry_delay);
+        let operation = tokio::spawn(async move {
+            let mut attempts = 0;
+            loop {
+                attempts += 1;
+                match op(input.clone()).await {
+                    Err(e) if e.is_retryable() && attempts <= retries => {
+                        let delay = retry_delay.saturating_mul(2u32.saturating_pow(attempts - 1));
+                        log::info!("retrying {label} in {delay:?}: {e}");
+                        tokio::time::sleep(delay).await;
+                    }
+                    result => return (attempts, result),
+                }
+            }
+        });
+
+        // The operation runs in a task of its own, so that a panic is reported as its failure
+        self.tasks.spawn(async move {
+            let mut operation = AbortOnDrop(operation);
+            let (attempts, result) = (&mut operation.0)
+                .await
+                .unwrap_or_else(|e| (1, Err(Error::TaskFailed(e.to_string()))));
+            Finished {
+                index,
+                attempts,
+                result,
+            }
+        });
+
+        index
+    }
+
+    // Waits until there is room for another operation, returning the ones that finished. All
+    // operations that already finished are returned, so that a failure stops the run before the
+    // next operation starts even when there is room for it.
+    pub(crate) async fn wait_for_slot(&mut self) -> Vec<Finished<T>> {
+        let mut finished = Vec::new();
+        while let Some(res) = self.tasks.try_join_next() {
+            finished.extend(self.joined(res));
+        }
+        while self.tasks.len() >= self.options.concurrency.max(1) {
+            match self.join_next().await {
+                Some(item) => finished.push(item),
+                None => break,
+            }
+        }
+        finished
+    }
+
+    // Waits for all operations that are still running
+    pub(crate) async fn finish(&mut self) -> Vec<Finished<T>> {
+        let mut finished = Vec::new();
+        while let Some(item) = self.join_next().await {
+            finished.push(item);
+        }
+        finished
+    }
+
+    async fn join_next(&mut self) -> Option<Finished<T>> {
+        loop {
+            let res = self.tasks.join_next().await?;
+            if let Some(item) = self.joined(res) {
+                return Some(item);
+            }
+        }
+    }
+
+    fn joined(&mut self, res: Result<Finished<T>, JoinError>) -> Option<Finished<T>> {
+        match res {
+            Ok(item) => {
+                self.stopped |= item.result.is_err() && self.options.mode == BulkMode::FailFast;
+                Some(item)
+            }
+            // Only waiting for the operation, which reports its own panic, can not panic
+            Err(e) => {
+                log::error!("bulk task failed: {e}");
+                None
+            }
+        }
+    }
+}
+
+// Aborts an operation when the runner, and with it the task waiting for it, is dropped
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn puts(count: usize) -> Vec<BulkOp> {
+        (0..count)
+            .map(|i| BulkOp::Put {
+                path: PathBuf::from(format!("/nonexistent/root-s3-bulk/{i}")),
+                destination: ObjectLocation::new("bucket", format!("key-{i}"), Some(1)),
+                options: PutOptions::default(),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn failures_are_reported_per_operation() {
+        let client = Client::new("http://localhost:9000", "key", 1).unwrap();
+        let options = BulkOptions {
+            concurrency: 2,
+            ..Default::default()
+        };
+
+        let report = client.run_bulk(tokio_stream::iter(puts(5)), &options).await;
+
+        assert!(!report.stopped);
+        assert_eq!(report.failed().count(), 5);
+        let indexes = report.results.iter().map(|item| item.index);
+        assert!(indexes.eq(0..5));
+        // A missing file does not go away by trying again
+        assert!(report.results.iter().all(|item| item.attempts == 1));
+        assert_eq!(report.results[3].op.location().key, "key-3");
+    }
+
+    #[tokio::test]
+    async fn runner_retries_and_reports_panics() {
+        let options = BulkOptions {
+            retries: 2,
+            retry_delay: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let mut runner = Runner::new(&options);
+
+        runner.spawn("connection".to_string(), (), |()| async {
+            Err::<(), _>(Error::Connection("reset".to_string()))
+        });
+        runner.spawn("panic".to_string(), (), |()| async {
+            panic!("bug");
+        });
+        runner.spawn("ok".to_string(), (), |()| async { Ok(()) });
+
+        let mut finished = runner.finish().await;
+        finished.sort_by_key(|item| item.index);
+        let [connection, panicked, ok] = finished.as_slice() else {
+            panic!("{} finished", finished.len());
+        };
+        assert_eq!(connection.attempts, 3);
+        assert!(matches!(connection.result, Err(Error::Connection(_))));
+        assert!(matches!(panicked.result, Err(Error::TaskFailed(_))));
+        assert!(ok.result.is_ok());
+        assert!(!runner.stopped());
+    }
+
+    #[tokio::test]
+    async fn fail_fast_stops_taking_operations() {
+        let client = Client::new("http://localhost:9000", "key", 1).unwrap();
+        let options = BulkOptions {
+            concurrency: 1,
+            mode: BulkMode::FailFast,
+            ..Default::default()
+        };
+
+        let report = client.run_bulk(tokio_stream::iter(puts(5)), &options).await;
+
+        assert!(report.stopped);
+        assert_eq!(report.results.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn fail_fast_starts_nothing_after_a_failure() {
+        let client = Client::new("http://localhost:9000", "key", 1).unwrap();
+        let options = BulkOptions {
+            concurrency: 4,
+            mode: BulkMode::FailFast,
+            ..Default::default()
+        };
+        // The first put fails on its missing file long before the next one is taken
+        let ops = Box::pin(tokio_stream::iter(puts(5)).then(|op| async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            op
+        }));
+
+        let report = client.run_bulk(ops, &options).await;
+
+        assert!(report.stopped);
+        assert_eq!(report.results.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn fail_fast_only_stops_when_operations_are_left_out() {
+        let client = Client::new("http://localhost:9000", "key", 1).unwrap();
+        let options = BulkOptions {
+            concurrency: 4,
+            mode: BulkMode::FailFast,
+            ..Default::default()
+        };
+
+        // Nothing is left to start when the only operation fails
+        let report = client.run_bulk(tokio_stream::iter(puts(1)), &options).await;
+
+        assert!(!report.stopped);
+        assert_eq!(report.failed().count(), 1);
+    }
+}
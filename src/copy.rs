@@ -0,0 +1,515 @@
+use crate::{
+    multipart::{part_ranges, part_size},
+    options::encode_tagging,
+    Client, CopyOptions, Error, GetOptions, ObjectLocation, ProjectContext, PutOptions,
+};
+use aws_sdk_s3::{
+    operation::{copy_object::CopyObjectOutput, head_object::HeadObjectOutput},
+    types::{CompletedPart, MetadataDirective, TaggingDirective},
+};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::Serialize;
+use std::collections::HashMap;
+use tokio::task::JoinSet;
+
+// Characters that are kept as-is in the `x-amz-copy-source` header
+const COPY_SOURCE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Largest object that can be copied with a single `CopyObject`, 5 GiB.
+pub const MAX_SINGLE_COPY_SIZE: i64 = 5 * 1024 * 1024 * 1024;
+
+/// Preferred size of the parts of a multipart copy, 256 MiB.
+pub const COPY_PART_SIZE: i64 = 256 * 1024 * 1024;
+
+// Number of parts copied at the same time
+const COPY_CONCURRENCY: usize = 8;
+
+/// How an object was copied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CopyMethod {
+    /// Copied by the server, the data never left it.
+    ServerSide,
+    /// Downloaded from the source and uploaded to the destination by this client.
+    Streamed,
+}
+
+/// Result of [`Client::copy_object_between`].
+#[derive(Debug, Clone)]
+pub struct CopyOutcome {
+    pub method: CopyMethod,
+    /// `ETag` of the copy.
+    pub e_tag: Option<String>,
+}
+
+impl Client {
+    /// Copies an object to another location, possibly in another Root project or organisation.
+    ///
+    /// Within a project the copy is done server-side. A `CopyObject` is sent to the project of
+    /// the destination and its copy source only names a bucket and key, which the gateway looks
+    /// up in that same project, so copies across projects or organisations are streamed from
+    /// the source to the destination instead. They apply the metadata and tagging directives of
+    /// `options` the same way the server would.
+    pub async fn copy_object_between(
+        &self,
+        source: &ObjectLocation,
+        destination: &ObjectLocation,
+        options: CopyOptions,
+    ) -> Result<CopyOutcome, Error> {
+        if !self.same_project(source.project, destination.project) {
+            return self.streamed_copy(self, source, destination, options).await;
+        }
+
+        let res = self.server_side_copy(source, destination, options).await?;
+        Ok(CopyOutcome {
+            method: CopyMethod::ServerSide,
+            e_tag: res.copy_object_result.and_then(|r| r.e_tag),
+        })
+    }
+
+    pub(crate) async fn server_side_copy(
+        &self,
+        source: &ObjectLocation,
+        destination: &ObjectLocation,
+        options: CopyOptions,
+    ) -> Result<CopyObjectOutput, Error> {
+        let metadata_directive = options.resolved_metadata_directive();
+        let tagging_directive = options.resolved_tagging_directive();
+
+        let res = self
+            .s3_client
+            .copy_object()
+            .key(&destination.key)
+            .copy_source(copy_source(&source.bucket, &source.key))
+            .bucket(&destination.bucket)
+            .set_content_type(options.content_type)
+            .set_cache_control(options.cache_control)
+            .set_content_disposition(options.content_disposition)
+            .set_content_encoding(options.content_encoding)
+            .set_storage_class(options.storage_class)
+            .set_metadata(options.metadata)
+            .metadata_directive(metadata_directive)
+            .set_tagging(options.tagging.as_ref().map(encode_tagging))
+            .tagging_directive(tagging_directive)
+            .customize()
+            .interceptor(
+                self.interceptors_for("CopyObject", destination.project)
+                    .with_headers(&options.headers),
+            )
+            .send()
+            .await
+            .map_err(|e| self.map_sdk_error(e, destination.project, Error::ErrCopyObject))?;
+
+        Ok(res)
+    }
+
+    // Download the source with this client and upload it with `target`, which can be another
+    // client, e.g. for another Root instance
+    pub(crate) async fn streamed_copy(
+        &self,
+        target: &Client,
+        source: &ObjectLocation,
+        destination: &ObjectLocation,
+        options: CopyOptions,
+    ) -> Result<CopyOutcome, Error> {
+        let object = self
+            .get_object_in(
+                &source.bucket,
+                &source.key,
+                source.project,
+                GetOptions::default(),
+            )
+            .await?;
+
+        // Apply the directives the way the server would
+        let metadata_directive = options.resolved_metadata_directive();
+        let tagging_directive = options.resolved_tagging_directive();
+
+        let tagging = if tagging_directive == TaggingDirective::Replace {
+            options.tagging
+        } else if object.tag_count.unwrap_or_default() > 0 {
+            Some(
+                self.get_object_tagging_in(&source.bucket, &source.key, source.project)
+                    .await?,
+            )
+        } else {
+            None
+        };
+
+        let put_options = if metadata_directive == MetadataDirective::Replace {
+            PutOptions {
+                content_type: options.content_type,
+                cache_control: options.cache_control,
+                content_disposition: options.content_disposition,
+                content_encoding: options.content_encoding,
+                storage_class: options.storage_class,
+                metadata: options.metadata,
+                tagging,
+                headers: options.headers,
+            }
+        } else {
+            PutOptions {
+                content_type: object.content_type,
+                cache_control: object.cache_control,
+                content_disposition: object.content_disposition,
+                content_encoding: object.content_encoding,
+                storage_class: options.storage_class,
+                metadata: object.metadata,
+                tagging,
+                headers: options.headers,
+            }
+        };
+
+        // A single `PutObject` has the same size limit as a single `CopyObject`
+        let e_tag = if object.content_length.unwrap_or_default() > MAX_SINGLE_COPY_SIZE {
+            target
+                .upload_reader(destination, object.body.into_async_read(), put_options)
+                .await?
+                .e_tag
+        } else {
+            target
+                .put_object_stream(
+                    &destination.bucket,
+                    &destination.key,
+                    object.body,
+                    object.content_length,
+                    destination.project,
+                    put_options,
+                )
+                .await?
+                .e_tag
+        };
+
+        Ok(CopyOutcome {
+            method: CopyMethod::Streamed,
+            e_tag,
+        })
+    }
+
+    /// Replaces the user metadata of an object, by copying the object onto itself.
+    ///
+    /// The content headers (`Content-Type`, `Cache-Control`, ...) and storage class of the object
+    /// are kept, tags are copied as well.
+    pub async fn update_metadata(
+        &self,
+        bucket: &str,
+        key: &str,
+        metadata: HashMap<String, String>,
+        project_id: Option<i32>,
+    ) -> Result<CopyObjectOutput, Error> {
+        // Replacing the metadata also replaces the content headers, so send the current ones again
+        let head = self.head_object(bucket, key, project_id).await?;
+
+        let options = CopyOptions {
+            content_type: head.content_type,
+            cache_control: head.cache_control,
+            content_disposition: head.content_disposition,
+            content_encoding: head.content_encoding,
+            storage_class: head.storage_class,
+            metadata: Some(metadata),
+            metadata_directive: Some(MetadataDirective::Replace),
+            ..Default::default()
+        };
+
+        let location = ObjectLocation::new(bucket, key, project_id);
+        self.server_side_copy(&location, &location, options).await
+    }
+
+    /// Copies an object of any size server-side.
+    ///
+    /// Objects up to [`MAX_SINGLE_COPY_SIZE`] are copied with [`Client::copy_object_between`],
+    /// larger ones with [`Client::copy_large`]. Like [`Client::copy_object_between`], copies
+    /// across projects are streamed instead.
+    pub async fn copy(
+        &self,
+        source: &ObjectLocation,
+        destination: &ObjectLocation,
+        options: CopyOptions,
+    ) -> Result<CopyOutcome, Error> {
+        let head = self
+            .head_object_in(&source.bucket, &source.key, source.project)
+            .await?;
+
+        self.copy_with_head(source, destination, head, options)
+            .await
+    }
+
+    // Copy an object of which the `HEAD` is already known
+    pub(crate) async fn copy_with_head(
+        &self,
+        source: &ObjectLocation,
+        destination: &ObjectLocation,
+        head: HeadObjectOutput,
+        options: CopyOptions,
+    ) -> Result<CopyOutcome, Error> {
+        if head.content_length.unwrap_or_default() <= MAX_SINGLE_COPY_SIZE
+            || !self.same_project(source.project, destination.project)
+        {
+            return self.copy_object_between(source, destination, options).await;
+        }
+
+        self.multipart_copy(source, destination, head, options)
+            .await
+    }
+
+    /// Copies an object with a multipart upload, copying [`COPY_PART_SIZE`] ranges of the source
+    /// in parallel with `UploadPartCopy`.
+    ///
+    /// This is needed for objects larger than [`MAX_SINGLE_COPY_SIZE`], which can not be copied
+    /// with a single `CopyObject`. A multipart upload can not copy metadata and tags by itself,
+    /// so with the `COPY` directives they are read from the source and set on the upload.
+    /// The upload is aborted when any part fails. Like [`Client::copy_object_between`], copies
+    /// across projects are streamed instead.
+    pub async fn copy_large(
+        &self,
+        source: &ObjectLocation,
+        destination: &ObjectLocation,
+        options: CopyOptions,
+    ) -> Result<CopyOutcome, Error> {
+        if !self.same_project(source.project, destination.project) {
+            return self.streamed_copy(self, source, destination, options).await;
+        }
+
+        let head = self
+            .head_object_in(&source.bucket, &source.key, source.project)
+            .await?;
+
+        self.multipart_copy(source, destination, head, options)
+            .await
+    }
+
+    async fn multipart_copy(
+        &self,
+        source: &ObjectLocation,
+        destination: &ObjectLocation,
+        head: HeadObjectOutput,
+        options: CopyOptions,
+    ) -> Result<CopyOutcome, Error> {
+        let size = head.content_length.unwrap_or_default();
+        if size == 0 {
+            // A multipart upload needs at least one part
+            return self.copy_object_between(source, destination, options).await;
+        }
+
+        let metadata_directive = options.resolved_metadata_directive();
+        let tagging = if options.resolved_tagging_directive() == TaggingDirective::Replace {
+            options.tagging
+        } else {
+            let tags = self
+                .get_object_tagging_in(&source.bucket, &source.key, source.project)
+                .await?;
+            (!tags.is_empty()).then_some(tags)
+        };
+
+        let put_options = if metadata_directive == MetadataDirective::Replace {
+            PutOptions {
+                content_type: options.content_type,
+                cache_control: options.cache_control,
+                content_disposition: options.content_disposition,
+                content_encoding: options.content_encoding,
+                storage_class: options.storage_class,
+                metadata: options.metadata,
+                tagging,
+                headers: options.headers,
+            }
+        } else {
+            PutOptions {
+                content_type: head.content_type,
+                cache_control: head.cache_control,
+                content_disposition: head.content_disposition,
+                content_encoding: head.content_encoding,
+                storage_class: options.storage_class,
+                metadata: head.metadata,
+                tagging,
+                headers: options.headers,
+            }
+        };
+
+        let upload_id = self
+            .create_multipart_upload(
+                &destination.bucket,
+                &destination.key,
+                destination.project,
+                put_options,
+            )
+            .await?;
+
+        let res = self.copy_parts(source, destination, &upload_id, size).await;
+        let res = match res {
+            Ok(parts) => {
+                self.complete_multipart_upload(
+                    &destination.bucket,
+                    &destination.key,
+                    destination.project,
+                    &upload_id,
+                    parts,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+
+        match res {
+            Ok(res) => Ok(CopyOutcome {
+                method: CopyMethod::ServerSide,
+                e_tag: res.e_tag,
+            }),
+            Err(e) => {
+                if let Err(abort_err) = self
+                    .abort_multipart_upload(
+                        &destination.bucket,
+                        &destination.key,
+                        destination.project,
+                        &upload_id,
+                    )
+                    .await
+                {
+                    log::warn!("failed to abort multipart copy {upload_id}: {abort_err}");
+                }
+
+                Err(e)
+            }
+        }
+    }
+
+    // Copy all ranges of the source, at most `COPY_CONCURRENCY` at a time
+    async fn copy_parts(
+        &self,
+        source: &ObjectLocation,
+        destination: &ObjectLocation,
+        upload_id: &str,
+        size: i64,
+    ) -> Result<Vec<CompletedPart>, Error> {
+        let mut tasks = JoinSet::new();
+        let mut parts = Vec::new();
+
+        let ranges = part_ranges(size, part_size(size, COPY_PART_SIZE));
+        for (part_number, range) in (1..).zip(ranges) {
+            // Wait for a free slot, returning early (and dropping the other copies) on failure
+            while tasks.len() >= COPY_CONCURRENCY {
+                if let Some(res) = tasks.join_next().await {
+                    parts.push(res.map_err(|e| Error::TaskFailed(e.to_string()))??);
+                }
+            }
+
+            let client = self.clone();
+            let (source, destination) = (source.clone(), destination.clone());
+            let upload_id = upload_id.to_string();
+            tasks.spawn(async move {
+                client
+                    .upload_part_copy(&source, &destination, &upload_id, part_number, range)
+                    .await
+            });
+        }
+
+        while let Some(res) = tasks.join_next().await {
+            parts.push(res.map_err(|e| Error::TaskFailed(e.to_string()))??);
+        }
+
+        Ok(parts)
+    }
+
+    // Organisation and id of the project of a context, the organisation of the client for
+    // contexts without one
+    pub(crate) fn resolve_project(&self, p: ProjectContext) -> (Option<i32>, Option<i32>) {
+        let org_id = p
+            .org_id
+            .or_else(|| self.config.as_ref().map(|config| config.org_id));
+
+        (org_id, p.project_id)
+    }
+
+    // Whether two contexts point at the same project
+    pub(crate) fn same_project(&self, a: ProjectContext, b: ProjectContext) -> bool {
+        self.resolve_project(a) == self.resolve_project(b)
+    }
+}
+
+// Value of the `x-amz-copy-source` header, the key has to be url encoded
+pub(crate) fn copy_source(bucket: &str, key: &str) -> String {
+    format!("{bucket}/{}", utf8_percent_encode(key, COPY_SOURCE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::Recorder, AddressingStyle};
+
+    // Uri and copy source of the first request of a copy, which is aborted
+    async fn first_request(
+        source: &ObjectLocation,
+        destination: &ObjectLocation,
+        size: i64,
+    ) -> (String, Option<String>) {
+        let recorder = Recorder::default();
+        let client = Client::new("http://localhost:9000", "key", 1)
+            .unwrap()
+            .with_addressing_style(AddressingStyle::Path)
+            .with_interceptor(recorder.clone());
+        let head = HeadObjectOutput::builder().content_length(size).build();
+
+        let res = client
+            .copy_with_head(source, destination, head, CopyOptions::default())
+            .await;
+        assert!(res.is_err());
+        let requests = recorder.requests.lock().unwrap();
+        let (uri, headers) = requests.first().unwrap();
+        let copy_source = headers
+            .get("x-amz-copy-source")
+            .map(|value| value.to_str().unwrap().to_string());
+        (uri.clone(), copy_source)
+    }
+
+    #[tokio::test]
+    async fn copies_across_projects_never_send_a_copy_source() {
+        let source = ObjectLocation::new("bucket", "key", Some(2));
+        let other_project = ObjectLocation::new("archive", "copy", Some(3));
+        let other_organisation =
+            ObjectLocation::new("archive", "copy", ProjectContext::in_organisation(7, 2));
+
+        // Within a project the object is copied server-side
+        let same_project =
+            ObjectLocation::new("archive", "copy", ProjectContext::in_organisation(1, 2));
+        let (uri, copy_source) = first_request(&source, &same_project, 1).await;
+        assert!(
+            uri.contains("/organisations/1/projects/2/s3/archive/copy"),
+            "{uri}"
+        );
+        assert_eq!(copy_source.as_deref(), Some("bucket/key"));
+
+        // The gateway would look the source up in the project of the destination, the object
+        // is downloaded from its own project instead, whatever its size
+        for destination in [&other_project, &other_organisation] {
+            for size in [1, MAX_SINGLE_COPY_SIZE + 1] {
+                let (uri, copy_source) = first_request(&source, destination, size).await;
+                assert!(
+                    uri.contains("/organisations/1/projects/2/s3/bucket/key"),
+                    "{destination:?} {size}: {uri}"
+                );
+                assert_eq!(copy_source, None, "{destination:?} {size}");
+            }
+        }
+    }
+
+    #[test]
+    fn copy_source_encodes_the_key() {
+        let cases = [
+            ("bucket", "key", "bucket/key"),
+            ("bucket", "a/b/c.txt", "bucket/a/b/c.txt"),
+            ("bucket", "my file.txt", "bucket/my%20file.txt"),
+            ("bucket", "a+b&c=d?e#f", "bucket/a%2Bb%26c%3Dd%3Fe%23f"),
+            ("bucket", "-_.~", "bucket/-_.~"),
+            ("bucket", "café/🦀", "bucket/caf%C3%A9/%F0%9F%A6%80"),
+            ("bucket", "100%", "bucket/100%25"),
+        ];
+
+        for (bucket, key, expected) in cases {
+            assert_eq!(copy_source(bucket, key), expected);
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use crate::{
+    progress::ProgressReader, Client, Error, GetOptions, ObjectLocation, ProgressObserver,
+};
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+use tokio::io::{AsyncRead, AsyncWriteExt};
+
+/// Result of [`Client::download_file`].
+#[derive(Debug, Clone)]
+pub struct DownloadOutcome {
+    /// `ETag` of the object.
+    pub e_tag: Option<String>,
+    /// Size of the file.
+    pub size: i64,
+    /// Bytes of the file that were already downloaded by an earlier, failed download.
+    pub resumed: i64,
+}
+
+impl Client {
+    /// Downloads an object to the file at `path`, replacing it, and reports the bytes written
+    /// to `progress`.
+    ///
+    /// The object is written to `path` with a `.part` suffix, renamed to `path` once complete.
+    /// When a download fails the partial file is kept together with the `ETag` of the object,
+    /// and downloading the same object to the same path again only fetches the rest of it, as
+    /// long as the object did not change (checked with `If-Match`). Downloads of a range in
+    /// `options` start over.
+    pub async fn download_file(
+        &self,
+        source: &ObjectLocation,
+        path: &Path,
+        options: GetOptions,
+        progress: Option<Arc<dyn ProgressObserver>>,
+    ) -> Result<DownloadOutcome, Error> {
+        self.download(source, path, options, progress, None).await
+    }
+
+    // Download to the partial file of `path`, continuing an earlier download of the same
+    // object, and give the file the date `modified` before it is renamed to `path`
+    pub(crate) async fn download(
+        &self,
+        source: &ObjectLocation,
+        path: &Path,
+        options: GetOptions,
+        progress: Option<Arc<dyn ProgressObserver>>,
+        modified: Option<SystemTime>,
+    ) -> Result<DownloadOutcome, Error> {
+        let partial = partial_path(path);
+        let e_tag_path = e_tag_path(&partial);
+        let io_error = |path: &Path| {
+            let path = path.display().to_string();
+            move |e| Error::LocalFile(path, e)
+        };
+
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(io_error(parent))?;
+        }
+
+        let resumed = self
+            .resume_download(source, &partial, &e_tag_path, &options)
+            .await?;
+        let (object, resumed) = if let Some(resumed) = resumed {
+            resumed
+        } else {
+            let object = self
+                .get_object_in(&source.bucket, &source.key, source.project, options.clone())
+                .await?;
+            // Only a whole object can be continued
+            match object.e_tag() {
+                Some(e_tag) if options.range.is_none() => {
+                    tokio::fs::write(&e_tag_path, e_tag)
+                        .await
+                        .map_err(io_error(&e_tag_path))?;
+                }
+                _ => remove_if_exists(&e_tag_path).await?,
+            }
+            (object, 0)
+        };
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed > 0)
+            .truncate(resumed == 0)
+            .open(&partial)
+            .await
+            .map_err(io_error(&partial))?;
+
+        let body = object.body.into_async_read();
+        let mut reader: Box<dyn AsyncRead + Unpin + Send> = match progress {
+            Some(observer) => {
+                let total = object
+                    .content_length
+                    .and_then(|len| u64::try_from(len + resumed).ok());
+                let resumed = u64::try_from(resumed).unwrap_or_default();
+                Box::new(ProgressReader::new(body, total, observer).with_transferred(resumed))
+            }
+            None => Box::new(body),
+        };
+
+        let size = tokio::io::copy(&mut reader, &mut file)
+            .await
+            .map_err(io_error(&partial))?;
+        file.flush().await.map_err(io_error(&partial))?;
+
+        let file = file.into_std().await;
+        if let Some(modified) = modified {
+            file.set_modified(modified).map_err(io_error(&partial))?;
+        }
+        drop(file);
+        tokio::fs::rename(&partial, path)
+            .await
+            .map_err(io_error(path))?;
+        remove_if_exists(&e_tag_path).await?;
+
+        Ok(DownloadOutcome {
+            e_tag: object.e_tag,
+            size: i64::try_from(size)
+                .unwrap_or(i64::MAX)
+                .saturating_add(resumed),
+            resumed,
+        })
+    }
+
+    // The rest of the object of a partial file and the size of that file, `None` when there
+    // is nothing to continue or the object changed since
+    async fn resume_download(
+        &self,
+        source: &ObjectLocation,
+        partial: &Path,
+        e_tag_path: &Path,
+        options: &GetOptions,
+    ) -> Result<Option<(aws_sdk_s3::operation::get_object::GetObjectOutput, i64)>, Error> {
+        if options.range.is_some() {
+            return Ok(None);
+        }
+        let (Ok(meta), Ok(e_tag)) = (
+            tokio::fs::metadata(partial).await,
+            tokio::fs::read_to_string(e_tag_path).await,
+        ) else {
+            return Ok(None);
+        };
+        let resumed = i64::try_from(meta.len()).unwrap_or(i64::MAX);
+        if resumed == 0
+            || options
+                .if_match
+                .as_ref()
+                .is_some_and(|if_match| *if_match != e_tag)
+        {
+            return Ok(None);
+        }
+
+        let options = GetOptions {
+            range: Some(format!("bytes={resumed}-")),
+            if_match: Some(e_tag),
+            ..options.clone()
+        };
+        match self
+            .get_object_in(&source.bucket, &source.key, source.project, options)
+            .await
+        {
+            Ok(object) if continues_at(object.content_range(), resumed) => {
+                log::info!(
+                    "resuming download of {} at {resumed} bytes",
+                    partial.display()
+                );
+                Ok(Some((object, resumed)))
+            }
+            // A server that ignores the range sends the whole object, which appended to the
+            // partial file would corrupt it
+            Ok(object) => {
+                log::info!(
+                    "restarting download of {}: asked for bytes {resumed}-, got {:?}",
+                    partial.display(),
+                    object.content_range()
+                );
+                Ok(None)
+            }
+            // The object changed, or the partial file is no shorter than it
+            Err(e) if matches!(e.code(), Some("PreconditionFailed" | "InvalidRange")) => {
+                log::info!("restarting download of {}: {e}", partial.display());
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// Whether a ranged response starts right after the `resumed` bytes of the partial file
+fn continues_at(content_range: Option<&str>, resumed: i64) -> bool {
+    content_range.is_some_and(|range| range.starts_with(&format!("bytes {resumed}-")))
+}
+
+/// Path a download to `path` is written to until it is complete.
+#[must_use]
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+/// Whether `path` is the partial file of a download, or the `ETag` kept next to it.
+#[must_use]
+pub fn is_partial(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    [".part", ".part.etag"]
+        .iter()
+        .any(|suffix| name.len() > suffix.len() && name.ends_with(suffix))
+}
+
+// `ETag` of the object a partial file belongs to
+fn e_tag_path(partial: &Path) -> PathBuf {
+    let mut name = partial.file_name().unwrap_or_default().to_os_string();
+    name.push(".etag");
+    partial.with_file_name(name)
+}
+
+async fn remove_if_exists(path: &Path) -> Result<(), Error> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            Err(Error::LocalFile(path.display().to_string(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_files_sit_next_to_the_download() {
+        let partial = partial_path(Path::new("dir/a.bin"));
+
+        assert_eq!(partial, Path::new("dir/a.bin.part"));
+        assert_eq!(e_tag_path(&partial), Path::new("dir/a.bin.part.etag"));
+        assert!(is_partial(&partial));
+        assert!(is_partial(&e_tag_path(&partial)));
+        assert!(!is_partial(Path::new("dir/a.bin")));
+        assert!(!is_partial(Path::new("dir/part")));
+    }
+
+    #[test]
+    fn continues_at_cases() {
+        let cases = [
+            (Some("bytes 100-199/200"), 100, true),
+            (Some("bytes 100-199/*"), 100, true),
+            (Some("bytes 0-199/200"), 100, false),
+            (Some("bytes 1000-1999/2000"), 100, false),
+            (Some("bytes */200"), 100, false),
+            (None, 100, false),
+        ];
+        for (content_range, resumed, expected) in cases {
+            assert_eq!(
+                continues_at(content_range, resumed),
+                expected,
+                "{content_range:?} at {resumed}"
+            );
+        }
+    }
+}
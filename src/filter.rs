@@ -0,0 +1,278 @@
+use crate::{Client, Error, ProjectContext};
+use aws_sdk_s3::{
+    primitives::DateTime,
+    types::{Object, ObjectStorageClass},
+};
+use regex::Regex;
+use std::cmp::Ordering;
+use tokio_stream::{Stream, StreamExt};
+
+/// Client-side filter on the objects of a listing, see [`Client::find_objects`].
+///
+/// All conditions that are set have to match, `ObjectFilter::default()` matches every object.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectFilter {
+    /// Patterns the full key has to match.
+    pub key_patterns: Vec<Regex>,
+    /// Pattern the last segment of the key (after the last `/`) has to match.
+    pub name_pattern: Option<Regex>,
+    /// Smallest size in bytes, inclusive.
+    pub min_size: Option<i64>,
+    /// Largest size in bytes, inclusive.
+    pub max_size: Option<i64>,
+    /// Only objects modified at or after this time.
+    pub modified_after: Option<DateTime>,
+    /// Only objects modified before this time.
+    pub modified_before: Option<DateTime>,
+    pub storage_class: Option<ObjectStorageClass>,
+}
+
+impl ObjectFilter {
+    /// Only match keys matching a glob, where `*` and `?` do not match `/` and `**` matches
+    /// anything.
+    pub fn key_glob(mut self, glob: &str) -> Result<Self, Error> {
+        self.key_patterns.push(glob_to_regex(glob)?);
+        Ok(self)
+    }
+
+    /// Only match keys matching a regular expression anywhere in the key.
+    pub fn key_regex(mut self, regex: &str) -> Result<Self, Error> {
+        let regex = Regex::new(regex).map_err(|e| Error::InvalidPattern(e.to_string()))?;
+        self.key_patterns.push(regex);
+        Ok(self)
+    }
+
+    /// Only match objects whose name, the part of the key after the last `/`, matches a glob.
+    pub fn name_glob(mut self, glob: &str) -> Result<Self, Error> {
+        self.name_pattern = Some(glob_to_regex(glob)?);
+        Ok(self)
+    }
+
+    #[must_use]
+    pub fn matches(&self, object: &Object) -> bool {
+        let key = object.key().unwrap_or_default();
+        let name = key.rsplit('/').next().unwrap_or(key);
+        let size = object.size().unwrap_or_default();
+
+        self.key_patterns
+            .iter()
+            .all(|pattern| pattern.is_match(key))
+            && self
+                .name_pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(name))
+            && self.min_size.is_none_or(|min| size >= min)
+            && self.max_size.is_none_or(|max| size <= max)
+            && self
+                .modified_after
+                .is_none_or(|after| object.last_modified().is_some_and(|date| *date >= after))
+            && self
+                .modified_before
+                .is_none_or(|before| object.last_modified().is_some_and(|date| *date < before))
+            && self.storage_class.as_ref().is_none_or(|class| {
+                // Objects without a storage class are STANDARD
+                object
+                    .storage_class()
+                    .unwrap_or(&ObjectStorageClass::Standard)
+                    == class
+            })
+    }
+}
+
+/// Order of the objects returned by [`sort_objects`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortBy {
+    #[default]
+    Key,
+    Size,
+    LastModified,
+}
+
+impl std::str::FromStr for SortBy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "key" | "name" => Ok(Self::Key),
+            "size" => Ok(Self::Size),
+            "date" | "modified" | "last-modified" => Ok(Self::LastModified),
+            _ => Err(Error::InvalidSortOrder(s.to_owned())),
+        }
+    }
+}
+
+/// Sorts objects in ascending order, objects that compare equal are sorted by key.
+pub fn sort_objects(objects: &mut [Object], by: SortBy) {
+    objects.sort_by(|a, b| {
+        let order = match by {
+            SortBy::Key => Ordering::Equal,
+            SortBy::Size => a.size().cmp(&b.size()),
+            SortBy::LastModified => a
+                .last_modified()
+                .partial_cmp(&b.last_modified())
+                .unwrap_or(Ordering::Equal),
+        };
+
+        order.then_with(|| a.key().cmp(&b.key()))
+    });
+}
+
+impl Client {
+    /// Lists all objects under a prefix that match `filter`.
+    ///
+    /// The filter is applied by the client, all objects under the prefix are still listed.
+    pub fn find_objects(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        project: impl Into<ProjectContext>,
+        filter: ObjectFilter,
+    ) -> impl Stream<Item = Result<Object, Error>> {
+        self.list_objects_stream(bucket, prefix, project)
+            .filter(move |object| {
+                object
+                    .as_ref()
+                    .map_or(true, |object| filter.matches(object))
+            })
+    }
+}
+
+// Regex matching the whole of a string when the glob does
+pub(crate) fn glob_to_regex(glob: &str) -> Result<Regex, Error> {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // `a/**/b` also matches `a/b`
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                // Character class, copied as is up to the closing bracket
+                regex.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    regex.push('^');
+                }
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        closed = true;
+                        break;
+                    }
+                    if c == '\\' {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                }
+                if !closed {
+                    return Err(Error::InvalidPattern(format!("{glob:?}: unclosed [")));
+                }
+                regex.push(']');
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+
+    Regex::new(&regex).map_err(|e| Error::InvalidPattern(format!("{glob:?}: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(key: &str, size: i64, modified: i64) -> Object {
+        Object::builder()
+            .key(key)
+            .size(size)
+            .last_modified(DateTime::from_secs(modified))
+            .build()
+    }
+
+    #[test]
+    fn glob_to_regex_cases() {
+        let cases = [
+            ("*.csv", "report.csv", true),
+            ("*.csv", "2024/report.csv", false),
+            ("**.csv", "2024/report.csv", true),
+            ("logs/**/*.gz", "logs/2024/05/app.gz", true),
+            ("logs/**/*.gz", "logs/app.txt", false),
+            ("logs/**/*.gz", "logs/app.gz", true),
+            ("logs/**/*.gz", "logsapp.gz", false),
+            ("**/*.gz", "app.gz", true),
+            ("**/*.gz", "a/b/app.gz", true),
+            ("logs/**", "logs/a/b", true),
+            ("file?.txt", "file1.txt", true),
+            ("file?.txt", "file10.txt", false),
+            ("[ab]*.txt", "b.txt", true),
+            ("[!ab]*.txt", "b.txt", false),
+            ("a+b (1).txt", "a+b (1).txt", true),
+        ];
+
+        for (glob, key, expected) in cases {
+            let regex = glob_to_regex(glob).unwrap();
+            assert_eq!(regex.is_match(key), expected, "{glob} on {key}");
+        }
+    }
+
+    #[test]
+    fn glob_to_regex_rejects_unclosed_classes() {
+        for glob in ["[ab", "*.[", "[!"] {
+            assert!(
+                matches!(glob_to_regex(glob), Err(Error::InvalidPattern(_))),
+                "{glob}"
+            );
+        }
+    }
+
+    #[test]
+    fn sort_by_from_str() {
+        assert_eq!("name".parse::<SortBy>().unwrap(), SortBy::Key);
+        assert_eq!("size".parse::<SortBy>().unwrap(), SortBy::Size);
+        assert_eq!("modified".parse::<SortBy>().unwrap(), SortBy::LastModified);
+        assert!(matches!(
+            "weight".parse::<SortBy>(),
+            Err(Error::InvalidSortOrder(order)) if order == "weight"
+        ));
+    }
+
+    #[test]
+    fn filter_combines_conditions() {
+        let filter = ObjectFilter {
+            min_size: Some(10),
+            modified_before: Some(DateTime::from_secs(100)),
+            ..Default::default()
+        }
+        .name_glob("*.csv")
+        .unwrap();
+
+        assert!(filter.matches(&object("data/a.csv", 10, 99)));
+        assert!(!filter.matches(&object("data/a.csv", 9, 99)));
+        assert!(!filter.matches(&object("data/a.csv", 10, 100)));
+        assert!(!filter.matches(&object("data/a.json", 10, 99)));
+        assert!(ObjectFilter::default().matches(&object("anything", 0, 0)));
+    }
+
+    #[test]
+    fn sort_objects_falls_back_to_key() {
+        let mut objects = vec![object("c", 1, 3), object("a", 2, 1), object("b", 1, 2)];
+
+        sort_objects(&mut objects, SortBy::Size);
+        let keys = objects.iter().map(|o| o.key().unwrap()).collect::<Vec<_>>();
+        assert_eq!(keys, ["b", "c", "a"]);
+
+        sort_objects(&mut objects, SortBy::LastModified);
+        let keys = objects.iter().map(|o| o.key().unwrap()).collect::<Vec<_>>();
+        assert_eq!(keys, ["a", "b", "c"]);
+    }
+}
@@ -0,0 +1,484 @@
+use crate::{Error, RootConfig};
+use aws_sdk_s3::config::{
+    interceptors::{
+        BeforeDeserializationInterceptorContextRef, BeforeTransmitInterceptorContextMut,
+    },
+    ConfigBag, Intercept, RuntimeComponents,
+};
+use aws_smithy_runtime_api::{
+    box_error::BoxError,
+    http::{Request, Response},
+};
+use std::sync::Arc;
+
+/// Information about the request an interceptor is called for.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    /// Name of the S3 operation, e.g. `PutObject`.
+    pub operation: &'static str,
+    /// Organisation of the project, `None` for the organisation of the client.
+    pub org_id: Option<i32>,
+    /// Root project the request is made for, if any.
+    pub project_id: Option<i32>,
+}
+
+/// Hook into every request made by a [`Client`](crate::Client).
+///
+/// Interceptors are called in the order they were registered with
+/// [`Client::with_interceptor`](crate::Client::with_interceptor). All methods have a default
+/// implementation that does nothing, so only the hooks that are needed have to be implemented.
+pub trait Interceptor: std::fmt::Debug + Send + Sync {
+    /// Called before the request is signed, e.g. to add headers or rewrite the uri.
+    /// Returning an error aborts the request.
+    fn modify_request(&self, req: &mut Request, ctx: &RequestContext) -> Result<(), BoxError> {
+        let _ = (req, ctx);
+        Ok(())
+    }
+
+    /// Called after the request is signed, right before it is sent. This is the place for
+    /// custom request signing. Returning an error aborts the request.
+    fn modify_before_transmit(
+        &self,
+        req: &mut Request,
+        ctx: &RequestContext,
+    ) -> Result<(), BoxError> {
+        let _ = (req, ctx);
+        Ok(())
+    }
+
+    /// Called with the raw response before it is parsed, e.g. for audit logging.
+    fn read_response(&self, res: &Response, ctx: &RequestContext) {
+        let _ = (res, ctx);
+    }
+}
+
+/// Built-in interceptor that authenticates requests with a Root API key and routes them to
+/// the S3 api of the project on the Root gateway.
+///
+/// Requests without a project id are left untouched.
+#[derive(Debug, Clone)]
+pub struct RootAuth {
+    config: RootConfig,
+}
+
+impl RootAuth {
+    #[must_use]
+    pub fn new(config: RootConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl Interceptor for RootAuth {
+    // Add the api key to the headers and route the request to the project on the Root gateway
+    fn modify_request(&self, req: &mut Request, ctx: &RequestContext) -> Result<(), BoxError> {
+        let Some(project_id) = ctx.project_id else {
+            return Ok(());
+        };
+
+        // Add the api key to the headers
+        req.headers_mut()
+            .append("x-api-key", self.config.api_key.clone());
+
+        let uri = rewrite_root_uri(
+            req.uri(),
+            &self.config.base_path,
+            ctx.org_id.unwrap_or(self.config.org_id),
+            project_id,
+        )?;
+        req.set_uri(uri.as_str())
+            .map_err(|_| Error::InvalidRequestUri(uri))?;
+
+        log::debug!("req: {:?}", req);
+
+        Ok(())
+    }
+}
+
+// Adds the custom headers of a single request
+#[derive(Debug)]
+struct ExtraHeaders(Vec<(String, String)>);
+
+impl Interceptor for ExtraHeaders {
+    fn modify_request(&self, req: &mut Request, _ctx: &RequestContext) -> Result<(), BoxError> {
+        for (name, value) in &self.0 {
+            req.headers_mut().try_insert(name.clone(), value.clone())?;
+        }
+
+        Ok(())
+    }
+}
+
+// Runs the interceptors of a client for a single request
+#[derive(Debug)]
+pub(crate) struct Interceptors {
+    pub(crate) interceptors: Vec<Arc<dyn Interceptor>>,
+    pub(crate) ctx: RequestContext,
+}
+
+impl Interceptors {
+    // Also send the given headers with the request
+    pub(crate) fn with_headers(mut self, headers: &[(String, String)]) -> Self {
+        if !headers.is_empty() {
+            self.interceptors
+                .push(Arc::new(ExtraHeaders(headers.to_vec())));
+        }
+
+        self
+    }
+}
+
+impl Intercept for Interceptors {
+    fn name(&self) -> &'static str {
+        "RootS3Interceptors"
+    }
+
+    fn modify_before_signing(
+        &self,
+        context: &mut BeforeTransmitInterceptorContextMut<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        for interceptor in &self.interceptors {
+            interceptor.modify_request(context.request_mut(), &self.ctx)?;
+        }
+
+        Ok(())
+    }
+
+    fn modify_before_transmit(
+        &self,
+        context: &mut BeforeTransmitInterceptorContextMut<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        for interceptor in &self.interceptors {
+            interceptor.modify_before_transmit(context.request_mut(), &self.ctx)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_before_deserialization(
+        &self,
+        context: &BeforeDeserializationInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        for interceptor in &self.interceptors {
+            interceptor.read_response(context.response(), &self.ctx);
+        }
+
+        Ok(())
+    }
+}
+
+// Rewrite an S3 request uri so it is routed to the S3 api of a project on the Root gateway.
+//
+// The Root path is inserted right after the base path of the endpoint, everything else (host,
+// port, the rest of the path, query and fragment) is kept byte for byte. Keys are already
+// percent-encoded by the SDK, so they must not be decoded or encoded again here. With path-style
+// addressing the bucket is the first segment of the remaining path, with virtual-hosted-style
+// addressing it is part of the host, either way it ends up in the right place.
+fn rewrite_root_uri(
+    uri: &str,
+    base_path: &str,
+    org_id: i32,
+    project_id: i32,
+) -> Result<String, Error> {
+    let invalid = || Error::InvalidRequestUri(uri.to_owned());
+
+    // `http::Uri` does not keep fragments, so split it off and put it back at the end
+    let (uri_without_fragment, fragment) = match uri.split_once('#') {
+        Some((rest, fragment)) => (rest, Some(fragment)),
+        None => (uri, None),
+    };
+    let parts = uri_without_fragment
+        .parse::<http::Uri>()
+        .map_err(|_| invalid())?
+        .into_parts();
+
+    let base_path = base_path.trim_end_matches('/');
+    let original_path = parts
+        .path_and_query
+        .as_ref()
+        .map_or("/", http::uri::PathAndQuery::path);
+    let remainder = original_path
+        .strip_prefix(base_path)
+        .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+        .ok_or_else(invalid)?;
+
+    let mut path_and_query =
+        format!("{base_path}/api/v1/organisations/{org_id}/projects/{project_id}/s3");
+
+    // If the original path contains more than just a slash, add it to the path
+    if remainder != "/" {
+        path_and_query += remainder;
+    }
+
+    // Put back query if there was one
+    if let Some(query) = parts.path_and_query.as_ref().and_then(|pq| pq.query()) {
+        path_and_query.push('?');
+        path_and_query += query;
+    }
+
+    let mut builder = http::Uri::builder().path_and_query(path_and_query);
+    if let Some(scheme) = parts.scheme {
+        builder = builder.scheme(scheme);
+    }
+    if let Some(authority) = parts.authority {
+        builder = builder.authority(authority);
+    }
+    let mut new_uri = builder.build().map_err(|_| invalid())?.to_string();
+
+    if let Some(fragment) = fragment {
+        new_uri.push('#');
+        new_uri += fragment;
+    }
+
+    Ok(new_uri)
+}
+
+// Path of the endpoint url, the Root api lives below it
+pub(crate) fn endpoint_base_path(url: &str) -> Result<String, Error> {
+    let uri = url.parse::<http::Uri>().map_err(|_| Error::InvalidUrl)?;
+    if uri.scheme().is_none() || uri.authority().is_none() {
+        return Err(Error::InvalidUrl);
+    }
+
+    Ok(uri.path().trim_end_matches('/').to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::Recorder, Client};
+    use std::sync::Mutex;
+
+    // Adds its name to the `x-trace` header and to `calls` in both hooks
+    #[derive(Debug)]
+    struct Tracer {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Interceptor for Tracer {
+        fn modify_request(&self, req: &mut Request, ctx: &RequestContext) -> Result<(), BoxError> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{} modify {}", self.name, ctx.operation));
+            req.headers_mut().append("x-trace", self.name);
+            Ok(())
+        }
+
+        fn modify_before_transmit(
+            &self,
+            req: &mut Request,
+            _ctx: &RequestContext,
+        ) -> Result<(), BoxError> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{} transmit", self.name));
+            req.headers_mut()
+                .append("x-transmit", format!("{}-signed", self.name));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn interceptors_run_in_order_before_signing_and_transmit() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder::default();
+        let client = Client::new("http://localhost:9000", "key", 1)
+            .unwrap()
+            .with_interceptor(Tracer {
+                name: "first",
+                calls: calls.clone(),
+            })
+            .with_interceptor(Tracer {
+                name: "second",
+                calls: calls.clone(),
+            })
+            .with_interceptor(recorder.clone());
+
+        assert!(client.head_object("bucket", "key", Some(2)).await.is_err());
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                "first modify HeadObject",
+                "second modify HeadObject",
+                "first transmit",
+                "second transmit"
+            ]
+        );
+        let requests = recorder.requests.lock().unwrap();
+        let [(uri, headers)] = requests.as_slice() else {
+            panic!("{requests:?}");
+        };
+        // The built-in Root interceptor runs first
+        assert_eq!(
+            uri,
+            "http://bucket.localhost:9000/api/v1/organisations/1/projects/2/s3/key"
+        );
+        assert_eq!(headers["x-api-key"], "key");
+        let values = |name| {
+            headers
+                .get_all(name)
+                .iter()
+                .map(|value| value.to_str().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(values("x-trace"), ["first", "second"]);
+        assert_eq!(values("x-transmit"), ["first-signed", "second-signed"]);
+        // Headers added before signing are signed, the ones added after are not
+        let authorization = headers["authorization"].to_str().unwrap();
+        assert!(authorization.contains("x-trace"), "{authorization}");
+        assert!(!authorization.contains("x-transmit"), "{authorization}");
+    }
+
+    #[test]
+    fn rewrite_root_uri_cases() {
+        // (endpoint, request uri, expected rewritten uri)
+        let cases = [
+            // List buckets, nothing after the root path
+            (
+                "http://localhost:9000",
+                "http://localhost:9000/",
+                "http://localhost:9000/api/v1/organisations/1/projects/2/s3",
+            ),
+            // Path-style
+            (
+                "http://localhost:9000",
+                "http://localhost:9000/bucket",
+                "http://localhost:9000/api/v1/organisations/1/projects/2/s3/bucket",
+            ),
+            (
+                "http://localhost:9000",
+                "http://localhost:9000/bucket/key",
+                "http://localhost:9000/api/v1/organisations/1/projects/2/s3/bucket/key",
+            ),
+            (
+                "http://localhost:9000",
+                "http://localhost:9000/bucket/a/b/c.txt?x-id=GetObject",
+                "http://localhost:9000/api/v1/organisations/1/projects/2/s3/bucket/a/b/c.txt?x-id=GetObject",
+            ),
+            (
+                "http://localhost:9000",
+                "http://localhost:9000/bucket?list-type=2&prefix=a%2Fb",
+                "http://localhost:9000/api/v1/organisations/1/projects/2/s3/bucket?list-type=2&prefix=a%2Fb",
+            ),
+            // Virtual-hosted-style, bucket in the host
+            (
+                "http://localhost:9000",
+                "http://bucket.localhost:9000/",
+                "http://bucket.localhost:9000/api/v1/organisations/1/projects/2/s3",
+            ),
+            (
+                "http://localhost:9000",
+                "http://bucket.localhost:9000/a/b/c.txt",
+                "http://bucket.localhost:9000/api/v1/organisations/1/projects/2/s3/a/b/c.txt",
+            ),
+            // Endpoint with a base path, with and without trailing slash
+            (
+                "https://example.com/storage",
+                "https://example.com/storage/bucket/key",
+                "https://example.com/storage/api/v1/organisations/1/projects/2/s3/bucket/key",
+            ),
+            (
+                "https://example.com/storage/",
+                "https://bucket.example.com/storage/key?uploads",
+                "https://bucket.example.com/storage/api/v1/organisations/1/projects/2/s3/key?uploads",
+            ),
+            (
+                "https://example.com/storage",
+                "https://example.com/storage",
+                "https://example.com/storage/api/v1/organisations/1/projects/2/s3",
+            ),
+            // Percent-encoded keys are kept as they are
+            (
+                "http://localhost:9000",
+                "http://localhost:9000/bucket/my%20file.txt",
+                "http://localhost:9000/api/v1/organisations/1/projects/2/s3/bucket/my%20file.txt",
+            ),
+            (
+                "http://localhost:9000",
+                "http://localhost:9000/bucket/caf%C3%A9/%F0%9F%A6%80",
+                "http://localhost:9000/api/v1/organisations/1/projects/2/s3/bucket/caf%C3%A9/%F0%9F%A6%80",
+            ),
+            (
+                "http://localhost:9000",
+                "http://localhost:9000/bucket/what%3F?tagging",
+                "http://localhost:9000/api/v1/organisations/1/projects/2/s3/bucket/what%3F?tagging",
+            ),
+            (
+                "http://localhost:9000",
+                "http://localhost:9000/bucket/a%2520b",
+                "http://localhost:9000/api/v1/organisations/1/projects/2/s3/bucket/a%2520b",
+            ),
+            // Fragments are kept
+            (
+                "http://localhost:9000",
+                "http://localhost:9000/bucket/key?acl#frag",
+                "http://localhost:9000/api/v1/organisations/1/projects/2/s3/bucket/key?acl#frag",
+            ),
+            // Ip addresses and default ports
+            (
+                "http://127.0.0.1:8080",
+                "http://127.0.0.1:8080/bucket/key",
+                "http://127.0.0.1:8080/api/v1/organisations/1/projects/2/s3/bucket/key",
+            ),
+            (
+                "https://example.com",
+                "https://example.com/bucket",
+                "https://example.com/api/v1/organisations/1/projects/2/s3/bucket",
+            ),
+        ];
+
+        for (endpoint, uri, expected) in cases {
+            let base_path = endpoint_base_path(endpoint).unwrap();
+            let rewritten = rewrite_root_uri(uri, &base_path, 1, 2).unwrap();
+            assert_eq!(rewritten, expected, "endpoint: {endpoint}, uri: {uri}");
+        }
+    }
+
+    #[test]
+    fn rewrite_root_uri_rejects_uri_outside_base_path() {
+        let cases = [
+            (
+                "https://example.com/storage",
+                "https://example.com/other/key",
+            ),
+            (
+                "https://example.com/storage",
+                "https://example.com/storagex/key",
+            ),
+            ("http://localhost:9000", "not a uri"),
+        ];
+
+        for (endpoint, uri) in cases {
+            let base_path = endpoint_base_path(endpoint).unwrap();
+            assert!(
+                matches!(
+                    rewrite_root_uri(uri, &base_path, 1, 2),
+                    Err(Error::InvalidRequestUri(_))
+                ),
+                "endpoint: {endpoint}, uri: {uri}"
+            );
+        }
+    }
+
+    #[test]
+    fn endpoint_base_path_requires_absolute_url() {
+        assert!(matches!(
+            endpoint_base_path("localhost"),
+            Err(Error::InvalidUrl)
+        ));
+        assert!(matches!(
+            endpoint_base_path("/storage"),
+            Err(Error::InvalidUrl)
+        ));
+        assert_eq!(endpoint_base_path("http://localhost:9000/").unwrap(), "");
+    }
+}
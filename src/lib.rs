@@ -1,21 +1,68 @@
 use anyhow::Result;
 use aws_credential_types::{provider::SharedCredentialsProvider, Credentials};
 use aws_sdk_s3::operation::{
+    abort_multipart_upload::AbortMultipartUploadError,
+    complete_multipart_upload::CompleteMultipartUploadError,
     copy_object::{CopyObjectError, CopyObjectOutput},
     create_bucket::{CreateBucketError, CreateBucketOutput},
+    create_multipart_upload::CreateMultipartUploadError,
     delete_bucket::{DeleteBucketError, DeleteBucketOutput},
     delete_object::{DeleteObjectError, DeleteObjectOutput},
     get_object::{GetObjectError, GetObjectOutput},
+    get_object_tagging::GetObjectTaggingError,
     head_object::{HeadObjectError, HeadObjectOutput},
     list_buckets::{ListBucketsError, ListBucketsOutput},
     list_objects_v2::{ListObjectsV2Error, ListObjectsV2Output},
+    list_parts::ListPartsError,
     put_object::{PutObjectError, PutObjectOutput},
+    put_object_tagging::{PutObjectTaggingError, PutObjectTaggingOutput},
+    restore_object::{RestoreObjectError, RestoreObjectOutput},
+    upload_part::UploadPartError,
+    upload_part_copy::UploadPartCopyError,
 };
-use aws_smithy_runtime_api::http::Request;
+use aws_sdk_s3::{
+    error::{DisplayErrorContext, ProvideErrorMetadata, SdkError},
+    primitives::ByteStream,
+    types::{RestoreRequest, Tag, Tagging},
+};
+use aws_smithy_runtime_api::client::{orchestrator::HttpResponse, result::CreateUnhandledError};
 use aws_types::{region::Region, sdk_config::SdkConfig};
-use std::collections::HashMap;
+use interceptor::{endpoint_base_path, Interceptors};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
 use thiserror::Error;
 
+pub mod bulk;
+pub mod copy;
+pub mod download;
+pub mod filter;
+pub mod interceptor;
+pub mod limit;
+pub mod listing;
+pub mod mirror;
+pub mod multipart;
+pub mod options;
+pub mod progress;
+pub mod rename;
+pub mod resume;
+pub mod sync;
+pub mod upload;
+
+pub use bulk::{BulkMode, BulkOp, BulkOptions, BulkOutput, BulkReport, BulkResult};
+pub use copy::{CopyMethod, CopyOutcome, MAX_SINGLE_COPY_SIZE};
+pub use download::DownloadOutcome;
+pub use filter::{sort_objects, ObjectFilter, SortBy};
+pub use interceptor::{Interceptor, RequestContext, RootAuth};
+pub use limit::{RateLimiter, RateLimits};
+pub use listing::Listing;
+pub use mirror::{MirrorReport, MirrorSummary, MirroredObject};
+pub use options::{CopyOptions, GetOptions, PutOptions};
+pub use progress::{Progress, ProgressObserver, ProgressReader};
+pub use rename::{FailedMove, MoveReport, MovedObject};
+pub use resume::{CheckpointPart, Checkpoints, UploadCheckpoint};
+pub use sync::{FailedSync, SyncCompare, SyncOptions, SyncReport, SyncedFile};
+pub use upload::UploadOutcome;
+
 /// `RootS3Client` struct represents a client for interacting with the S3 service of root.
 #[derive(Debug, Clone)]
 pub struct Client {
@@ -24,18 +71,120 @@ pub struct Client {
 
     /// Optional root config.
     pub config: Option<RootConfig>,
+
+    /// Interceptors called for every request, in order.
+    pub interceptors: Vec<Arc<dyn Interceptor>>,
+
+    /// Url the client was created with.
+    pub endpoint_url: String,
+
+    /// Limits on the transfer rate of uploaded and downloaded bodies.
+    pub rate_limits: RateLimits,
+}
+
+/// How the bucket is addressed in the request url.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AddressingStyle {
+    /// The bucket is the first segment of the path: `http://localhost:9000/bucket/key`.
+    /// Works with Root, `MinIO` and most local stand-ins without any DNS setup.
+    Path,
+    /// The bucket is part of the host: `http://bucket.localhost:9000/key`. The default of the
+    /// SDK, and of the clients of this crate.
+    #[default]
+    VirtualHosted,
+}
+
+impl std::str::FromStr for AddressingStyle {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "path" => Ok(Self::Path),
+            "virtual-hosted" | "virtual" => Ok(Self::VirtualHosted),
+            _ => Err(Error::InvalidAddressingStyle(s.to_owned())),
+        }
+    }
+}
+
+/// A Root project a request is made for.
+///
+/// An `Option<i32>` project id converts into a context in the organisation of the client.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectContext {
+    /// Organisation of the project, `None` uses the organisation of the client.
+    pub org_id: Option<i32>,
+    /// Id of the project, `None` sends the request without Root routing.
+    pub project_id: Option<i32>,
+}
+
+impl ProjectContext {
+    /// Project in the organisation of the client.
+    #[must_use]
+    pub fn project(project_id: i32) -> Self {
+        Self {
+            org_id: None,
+            project_id: Some(project_id),
+        }
+    }
+
+    /// Project in another organisation than the one of the client.
+    #[must_use]
+    pub fn in_organisation(org_id: i32, project_id: i32) -> Self {
+        Self {
+            org_id: Some(org_id),
+            project_id: Some(project_id),
+        }
+    }
+}
+
+impl From<Option<i32>> for ProjectContext {
+    fn from(project_id: Option<i32>) -> Self {
+        Self {
+            org_id: None,
+            project_id,
+        }
+    }
+}
+
+/// An object in a bucket of a Root project.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectLocation {
+    pub bucket: String,
+    pub key: String,
+    pub project: ProjectContext,
+}
+
+impl ObjectLocation {
+    #[must_use]
+    pub fn new(
+        bucket: impl Into<String>,
+        key: impl Into<String>,
+        project: impl Into<ProjectContext>,
+    ) -> Self {
+        Self {
+            bucket: bucket.into(),
+            key: key.into(),
+            project: project.into(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct RootConfig {
     pub api_key: String,
     pub org_id: i32,
+    /// Path of the endpoint url, the Root api path is added after it.
+    pub base_path: String,
 }
 
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Invalid url")]
     InvalidUrl,
+    #[error("Invalid addressing style {0:?}, expected \"path\" or \"virtual-hosted\"")]
+    InvalidAddressingStyle(String),
+    #[error("Invalid request uri: {0}")]
+    InvalidRequestUri(String),
     #[error("Failed to create bucket: {0}")]
     ErrCreateBucket(Box<CreateBucketError>),
     #[error("Failed to delete bucket: {0}")]
@@ -54,6 +203,108 @@ pub enum Error {
     ErrDeleteObject(Box<DeleteObjectError>),
     #[error("Failed to list objects: {0}")]
     ErrListObjects(Box<ListObjectsV2Error>),
+    #[error("Failed to get object tagging: {0}")]
+    ErrGetObjectTagging(Box<GetObjectTaggingError>),
+    #[error("Failed to put object tagging: {0}")]
+    ErrPutObjectTagging(Box<PutObjectTaggingError>),
+    #[error("Failed to restore object: {0}")]
+    ErrRestoreObject(Box<RestoreObjectError>),
+    #[error("Failed to create multipart upload: {0}")]
+    ErrCreateMultipartUpload(Box<CreateMultipartUploadError>),
+    #[error("Failed to upload part: {0}")]
+    ErrUploadPart(Box<UploadPartError>),
+    #[error("Failed to copy part: {0}")]
+    ErrUploadPartCopy(Box<UploadPartCopyError>),
+    #[error("Failed to complete multipart upload: {0}")]
+    ErrCompleteMultipartUpload(Box<CompleteMultipartUploadError>),
+    #[error("Failed to abort multipart upload: {0}")]
+    ErrAbortMultipartUpload(Box<AbortMultipartUploadError>),
+    #[error("Failed to list parts: {0}")]
+    ErrListParts(Box<ListPartsError>),
+    #[error("The data does not fit in the {0} parts of a multipart upload")]
+    TooManyParts(i64),
+    #[error("Failed to read the data to upload: {0}")]
+    Read(String),
+    #[error("{0}: {1}")]
+    LocalFile(String, #[source] std::io::Error),
+    #[error("Can not resume the upload of {0}: {1}")]
+    StaleCheckpoint(String, String),
+    #[error("The upload of {0} was started {1}, it can only be continued there")]
+    ForeignCheckpoint(String, String),
+    #[error("Key {0:?} would be written outside of the destination directory")]
+    UnsafeKey(String),
+    #[error("The server did not return an upload id for the multipart upload")]
+    MissingUploadId,
+    #[error("Background task failed: {0}")]
+    TaskFailed(String),
+    #[error("Invalid tags: {0}")]
+    InvalidTagging(String),
+    #[error("Invalid pattern: {0}")]
+    InvalidPattern(String),
+    #[error("Invalid sort order {0:?}, expected \"key\", \"size\" or \"date\"")]
+    InvalidSortOrder(String),
+    #[error("Invalid move: {0}")]
+    InvalidMove(String),
+    #[error("Invalid mirror: {0}")]
+    InvalidMirror(String),
+    #[error("Copy of {0} does not match the source: {1}")]
+    MoveVerificationFailed(String, String),
+    #[error("The API key was rejected by the Root gateway")]
+    InvalidApiKey,
+    #[error("Organisation {0} does not exist")]
+    UnknownOrganisation(i32),
+    #[error("Project {0} does not exist")]
+    UnknownProject(i32),
+    #[error("Access to project {0} is forbidden for this API key")]
+    ForbiddenProject(i32),
+    #[error("Could not reach the server: {0}")]
+    Connection(String),
+}
+
+impl Error {
+    /// S3 error code of a request the server rejected, e.g. `NoSuchKey` or `AccessDenied`.
+    #[must_use]
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            Error::ErrCreateBucket(e) => e.code(),
+            Error::ErrDeleteBucket(e) => e.code(),
+            Error::ErrListBuckets(e) => e.code(),
+            Error::ErrPutObject(e) => e.code(),
+            Error::ErrCopyObject(e) => e.code(),
+            Error::ErrGetObject(e) => e.code(),
+            Error::ErrGetHeadObject(e) => e.code(),
+            Error::ErrDeleteObject(e) => e.code(),
+            Error::ErrListObjects(e) => e.code(),
+            Error::ErrGetObjectTagging(e) => e.code(),
+            Error::ErrPutObjectTagging(e) => e.code(),
+            Error::ErrRestoreObject(e) => e.code(),
+            Error::ErrCreateMultipartUpload(e) => e.code(),
+            Error::ErrUploadPart(e) => e.code(),
+            Error::ErrUploadPartCopy(e) => e.code(),
+            Error::ErrCompleteMultipartUpload(e) => e.code(),
+            Error::ErrAbortMultipartUpload(e) => e.code(),
+            Error::ErrListParts(e) => e.code(),
+            _ => None,
+        }
+    }
+
+    /// Whether sending the request again may succeed: the server could not be reached, was
+    /// busy or failed internally.
+    #[must_use]
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Error::Connection(_))
+            || matches!(
+                self.code(),
+                Some(
+                    "InternalError"
+                        | "ServiceUnavailable"
+                        | "SlowDown"
+                        | "RequestTimeout"
+                        | "Throttling"
+                        | "ThrottlingException"
+                )
+            )
+    }
 }
 
 pub struct S3Credentials {
@@ -81,14 +332,23 @@ impl Client {
         api_key: impl Into<String>,
         org_id: i32,
     ) -> Result<Self, Error> {
-        let s3_client = get_s3_client(&url.into(), None).map_err(|_| Error::InvalidUrl)?;
+        let url = url.into();
+        let base_path = endpoint_base_path(&url)?;
+        let s3_client = get_s3_client_with_addressing_style(&url, None, AddressingStyle::default())
+            .map_err(|_| Error::InvalidUrl)?;
+
+        let config = RootConfig {
+            api_key: api_key.into(),
+            org_id,
+            base_path,
+        };
 
         Ok(Self {
-            config: Some(RootConfig {
-                api_key: api_key.into(),
-                org_id,
-            }),
+            interceptors: vec![Arc::new(RootAuth::new(config.clone()))],
+            config: Some(config),
             s3_client,
+            endpoint_url: url,
+            rate_limits: RateLimits::default(),
         })
     }
 
@@ -96,29 +356,74 @@ impl Client {
         url: impl Into<String> + Clone,
         credentials: S3Credentials,
     ) -> Result<Self, Error> {
-        let s3_client =
-            get_s3_client(&url.into(), Some(credentials)).map_err(|_| Error::InvalidUrl)?;
+        let url = url.into();
+        let s3_client = get_s3_client_with_addressing_style(
+            &url,
+            Some(credentials),
+            AddressingStyle::default(),
+        )
+        .map_err(|_| Error::InvalidUrl)?;
 
         Ok(Self {
             config: None,
+            interceptors: Vec::new(),
             s3_client,
+            endpoint_url: url,
+            rate_limits: RateLimits::default(),
         })
     }
+
+    /// Registers an interceptor that is called for every request made by this client, after the
+    /// interceptors that were registered before it.
+    #[must_use]
+    pub fn with_interceptor(mut self, interceptor: impl Interceptor + 'static) -> Self {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Switches the client to the given addressing style.
+    ///
+    /// Clients use virtual-hosted-style addressing by default. The Root path rewriting works
+    /// the same for both styles, so this only changes where the SDK puts the bucket name.
+    #[must_use]
+    pub fn with_addressing_style(mut self, addressing_style: AddressingStyle) -> Self {
+        let config = self
+            .s3_client
+            .config()
+            .to_builder()
+            .force_path_style(addressing_style == AddressingStyle::Path)
+            .build();
+        self.s3_client = aws_sdk_s3::Client::from_conf(config);
+        self
+    }
 }
 
+/// Builds an S3 client with the default addressing of the SDK, virtual-hosted-style.
 pub fn get_s3_client(url: &str, credentials: Option<S3Credentials>) -> Result<aws_sdk_s3::Client> {
+    get_s3_client_with_addressing_style(url, credentials, AddressingStyle::default())
+}
+
+/// Builds an S3 client putting the bucket in the url as `addressing_style` says.
+pub fn get_s3_client_with_addressing_style(
+    url: &str,
+    credentials: Option<S3Credentials>,
+    addressing_style: AddressingStyle,
+) -> Result<aws_sdk_s3::Client> {
     let cred = match credentials {
         Some(cred) => Credentials::new(cred.access_key_id, cred.secret_access_key, None, None, ""),
         None => Credentials::new("", "", None, None, ""),
     };
 
-    let client = aws_sdk_s3::Client::new(
-        &SdkConfig::builder()
-            .endpoint_url(url)
-            .region(Region::new("weur"))
-            .credentials_provider(SharedCredentialsProvider::new(cred))
-            .build(),
-    );
+    let sdk_config = SdkConfig::builder()
+        .endpoint_url(url)
+        .region(Region::new("weur"))
+        .credentials_provider(SharedCredentialsProvider::new(cred))
+        .build();
+
+    let config = aws_sdk_s3::config::Builder::from(&sdk_config)
+        .force_path_style(addressing_style == AddressingStyle::Path)
+        .build();
+    let client = aws_sdk_s3::Client::from_conf(config);
 
     Ok(client)
 }
@@ -129,17 +434,15 @@ impl Client {
         bucket: &str,
         project_id: Option<i32>,
     ) -> Result<CreateBucketOutput, Error> {
-        let config = self.config.clone();
-
         let res = self
             .s3_client
             .create_bucket()
             .bucket(bucket)
             .customize()
-            .mutate_request(move |req| add_root_auth(req, &config, project_id))
+            .interceptor(self.interceptors_for("CreateBucket", project_id))
             .send()
             .await
-            .map_err(|e| Error::ErrCreateBucket(Box::new(e.into_service_error())))?;
+            .map_err(|e| self.map_sdk_error(e, project_id, Error::ErrCreateBucket))?;
 
         Ok(res)
     }
@@ -149,32 +452,28 @@ impl Client {
         bucket: &str,
         project_id: Option<i32>,
     ) -> Result<DeleteBucketOutput, Error> {
-        let config = self.config.clone();
-
         let res = self
             .s3_client
             .delete_bucket()
             .bucket(bucket)
             .customize()
-            .mutate_request(move |req| add_root_auth(req, &config, project_id))
+            .interceptor(self.interceptors_for("DeleteBucket", project_id))
             .send()
             .await
-            .map_err(|e| Error::ErrDeleteBucket(Box::new(e.into_service_error())))?;
+            .map_err(|e| self.map_sdk_error(e, project_id, Error::ErrDeleteBucket))?;
 
         Ok(res)
     }
 
     pub async fn list_buckets(&self, project_id: Option<i32>) -> Result<ListBucketsOutput, Error> {
-        let config = self.config.clone();
-
         let res = self
             .s3_client
             .list_buckets()
             .customize()
-            .mutate_request(move |req| add_root_auth(req, &config, project_id))
+            .interceptor(self.interceptors_for("ListBuckets", project_id))
             .send()
             .await
-            .map_err(|e| Error::ErrListBuckets(Box::new(e.into_service_error())))?;
+            .map_err(|e| self.map_sdk_error(e, project_id, Error::ErrListBuckets))?;
 
         Ok(res)
     }
@@ -185,22 +484,46 @@ impl Client {
         key: &str,
         data: bytes::Bytes,
         project_id: Option<i32>,
-        metadata: Option<HashMap<String, String>>,
+        options: PutOptions,
+    ) -> Result<PutObjectOutput, Error> {
+        self.put_object_stream(bucket, key, data.into(), None, project_id, options)
+            .await
+    }
+
+    // Upload a body that is not necessarily in memory, like the body of another object
+    pub(crate) async fn put_object_stream(
+        &self,
+        bucket: &str,
+        key: &str,
+        body: ByteStream,
+        content_length: Option<i64>,
+        project: impl Into<ProjectContext>,
+        options: PutOptions,
     ) -> Result<PutObjectOutput, Error> {
-        let config = self.config.clone();
+        let project = project.into();
 
         let res = self
             .s3_client
             .put_object()
             .key(key)
-            .body(data.into())
+            .body(self.throttle("PutObject", body))
+            .set_content_length(content_length)
             .bucket(bucket)
-            .set_metadata(metadata)
+            .set_content_type(options.content_type)
+            .set_cache_control(options.cache_control)
+            .set_content_disposition(options.content_disposition)
+            .set_content_encoding(options.content_encoding)
+            .set_storage_class(options.storage_class)
+            .set_metadata(options.metadata)
+            .set_tagging(options.tagging.as_ref().map(options::encode_tagging))
             .customize()
-            .mutate_request(move |req| add_root_auth(req, &config, project_id))
+            .interceptor(
+                self.interceptors_for("PutObject", project)
+                    .with_headers(&options.headers),
+            )
             .send()
             .await
-            .map_err(|e| Error::ErrPutObject(Box::new(e.into_service_error())))?;
+            .map_err(|e| self.map_sdk_error(e, project, Error::ErrPutObject))?;
 
         Ok(res)
     }
@@ -212,22 +535,14 @@ impl Client {
         target_bucket: &str,
         target_key: &str,
         project_id: Option<i32>,
+        options: CopyOptions,
     ) -> Result<CopyObjectOutput, Error> {
-        let config = self.config.clone();
-
-        let res = self
-            .s3_client
-            .copy_object()
-            .key(target_key)
-            .copy_source(format!("{bucket}/{key}"))
-            .bucket(target_bucket)
-            .customize()
-            .mutate_request(move |req| add_root_auth(req, &config, project_id))
-            .send()
-            .await
-            .map_err(|e| Error::ErrCopyObject(Box::new(e.into_service_error())))?;
-
-        Ok(res)
+        self.server_side_copy(
+            &ObjectLocation::new(bucket, key, project_id),
+            &ObjectLocation::new(target_bucket, target_key, project_id),
+            options,
+        )
+        .await
     }
 
     pub async fn get_object(
@@ -235,19 +550,37 @@ impl Client {
         bucket: &str,
         key: &str,
         project_id: Option<i32>,
+        options: GetOptions,
     ) -> Result<GetObjectOutput, Error> {
-        let config = self.config.clone();
+        self.get_object_in(bucket, key, project_id, options).await
+    }
 
-        let res = self
+    pub(crate) async fn get_object_in(
+        &self,
+        bucket: &str,
+        key: &str,
+        project: impl Into<ProjectContext>,
+        options: GetOptions,
+    ) -> Result<GetObjectOutput, Error> {
+        let project = project.into();
+
+        let mut res = self
             .s3_client
             .get_object()
             .key(key)
             .bucket(bucket)
+            .set_range(options.range)
+            .set_if_match(options.if_match)
+            .set_if_none_match(options.if_none_match)
             .customize()
-            .mutate_request(move |req| add_root_auth(req, &config, project_id))
+            .interceptor(
+                self.interceptors_for("GetObject", project)
+                    .with_headers(&options.headers),
+            )
             .send()
             .await
-            .map_err(|e| Error::ErrGetObject(Box::new(e.into_service_error())))?;
+            .map_err(|e| self.map_sdk_error(e, project, Error::ErrGetObject))?;
+        res.body = self.throttle("GetObject", res.body);
 
         Ok(res)
     }
@@ -258,7 +591,16 @@ impl Client {
         key: &str,
         project_id: Option<i32>,
     ) -> Result<DeleteObjectOutput, Error> {
-        let config = self.config.clone();
+        self.delete_object_in(bucket, key, project_id).await
+    }
+
+    pub(crate) async fn delete_object_in(
+        &self,
+        bucket: &str,
+        key: &str,
+        project: impl Into<ProjectContext>,
+    ) -> Result<DeleteObjectOutput, Error> {
+        let project = project.into();
 
         let res = self
             .s3_client
@@ -266,10 +608,10 @@ impl Client {
             .key(key)
             .bucket(bucket)
             .customize()
-            .mutate_request(move |req| add_root_auth(req, &config, project_id))
+            .interceptor(self.interceptors_for("DeleteObject", project))
             .send()
             .await
-            .map_err(|e| Error::ErrDeleteObject(Box::new(e.into_service_error())))?;
+            .map_err(|e| self.map_sdk_error(e, project, Error::ErrDeleteObject))?;
 
         Ok(res)
     }
@@ -280,18 +622,33 @@ impl Client {
         prefix: &str,
         project_id: Option<i32>,
     ) -> Result<ListObjectsV2Output, Error> {
-        let config = self.config.clone();
+        self.list_objects_page(bucket, prefix, None, None, project_id)
+            .await
+    }
+
+    // List a single page of objects, continuing after `continuation_token` if given
+    pub(crate) async fn list_objects_page(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        delimiter: Option<&str>,
+        continuation_token: Option<String>,
+        project: impl Into<ProjectContext>,
+    ) -> Result<ListObjectsV2Output, Error> {
+        let project = project.into();
 
         let res = self
             .s3_client
             .list_objects_v2()
             .bucket(bucket)
             .prefix(prefix)
+            .set_delimiter(delimiter.map(str::to_string))
+            .set_continuation_token(continuation_token)
             .customize()
-            .mutate_request(move |req| add_root_auth(req, &config, project_id))
+            .interceptor(self.interceptors_for("ListObjectsV2", project))
             .send()
             .await
-            .map_err(|e| Error::ErrListObjects(Box::new(e.into_service_error())))?;
+            .map_err(|e| self.map_sdk_error(e, project, Error::ErrListObjects))?;
 
         Ok(res)
     }
@@ -302,7 +659,16 @@ impl Client {
         key: &str,
         project_id: Option<i32>,
     ) -> Result<HeadObjectOutput, Error> {
-        let config = self.config.clone();
+        self.head_object_in(bucket, key, project_id).await
+    }
+
+    pub(crate) async fn head_object_in(
+        &self,
+        bucket: &str,
+        key: &str,
+        project: impl Into<ProjectContext>,
+    ) -> Result<HeadObjectOutput, Error> {
+        let project = project.into();
 
         let res = self
             .s3_client
@@ -310,64 +676,398 @@ impl Client {
             .key(key)
             .bucket(bucket)
             .customize()
-            .mutate_request(move |req| add_root_auth(req, &config, project_id))
+            .interceptor(self.interceptors_for("HeadObject", project))
+            .send()
+            .await
+            .map_err(|e| self.map_sdk_error(e, project, Error::ErrGetHeadObject))?;
+
+        Ok(res)
+    }
+
+    pub async fn get_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        project_id: Option<i32>,
+    ) -> Result<HashMap<String, String>, Error> {
+        self.get_object_tagging_in(bucket, key, project_id).await
+    }
+
+    pub(crate) async fn get_object_tagging_in(
+        &self,
+        bucket: &str,
+        key: &str,
+        project: impl Into<ProjectContext>,
+    ) -> Result<HashMap<String, String>, Error> {
+        let project = project.into();
+
+        let res = self
+            .s3_client
+            .get_object_tagging()
+            .key(key)
+            .bucket(bucket)
+            .customize()
+            .interceptor(self.interceptors_for("GetObjectTagging", project))
+            .send()
+            .await
+            .map_err(|e| self.map_sdk_error(e, project, Error::ErrGetObjectTagging))?;
+
+        Ok(res
+            .tag_set
+            .into_iter()
+            .map(|tag| (tag.key, tag.value))
+            .collect())
+    }
+
+    /// Replaces the tags of an object.
+    pub async fn put_object_tagging(
+        &self,
+        bucket: &str,
+        key: &str,
+        tags: &HashMap<String, String>,
+        project_id: Option<i32>,
+    ) -> Result<PutObjectTaggingOutput, Error> {
+        self.put_object_tagging_in(bucket, key, tags, project_id)
+            .await
+    }
+
+    pub(crate) async fn put_object_tagging_in(
+        &self,
+        bucket: &str,
+        key: &str,
+        tags: &HashMap<String, String>,
+        project: impl Into<ProjectContext>,
+    ) -> Result<PutObjectTaggingOutput, Error> {
+        let project = project.into();
+
+        let mut tags = tags.iter().collect::<Vec<_>>();
+        tags.sort();
+        let tag_set = tags
+            .into_iter()
+            .map(|(key, value)| Tag::builder().key(key).value(value).build())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::InvalidTagging(e.to_string()))?;
+        let tagging = Tagging::builder()
+            .set_tag_set(Some(tag_set))
+            .build()
+            .map_err(|e| Error::InvalidTagging(e.to_string()))?;
+
+        let res = self
+            .s3_client
+            .put_object_tagging()
+            .key(key)
+            .bucket(bucket)
+            .tagging(tagging)
+            .customize()
+            .interceptor(self.interceptors_for("PutObjectTagging", project))
+            .send()
+            .await
+            .map_err(|e| self.map_sdk_error(e, project, Error::ErrPutObjectTagging))?;
+
+        Ok(res)
+    }
+
+    /// Restores an archived object, e.g. of the `GLACIER` storage class, for `days` days.
+    pub async fn restore_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        days: i32,
+        project_id: Option<i32>,
+    ) -> Result<RestoreObjectOutput, Error> {
+        self.restore_object_in(bucket, key, days, project_id).await
+    }
+
+    pub(crate) async fn restore_object_in(
+        &self,
+        bucket: &str,
+        key: &str,
+        days: i32,
+        project: impl Into<ProjectContext>,
+    ) -> Result<RestoreObjectOutput, Error> {
+        let project = project.into();
+
+        let res = self
+            .s3_client
+            .restore_object()
+            .key(key)
+            .bucket(bucket)
+            .restore_request(RestoreRequest::builder().days(days).build())
+            .customize()
+            .interceptor(self.interceptors_for("RestoreObject", project))
             .send()
             .await
-            .map_err(|e| Error::ErrGetHeadObject(Box::new(e.into_service_error())))?;
+            .map_err(|e| self.map_sdk_error(e, project, Error::ErrRestoreObject))?;
 
         Ok(res)
     }
 }
 
-// Add the api key to the headers and the project id to the query
-// Only do this if an api key is set
-fn add_root_auth(req: &mut Request, config: &Option<RootConfig>, project_id: Option<i32>) {
-    if config.is_none() {
-        return;
-    };
+impl Client {
+    // Interceptors to register on a single request
+    fn interceptors_for(
+        &self,
+        operation: &'static str,
+        project: impl Into<ProjectContext>,
+    ) -> Interceptors {
+        let project = project.into();
 
-    if project_id.is_none() {
-        return;
-    };
+        Interceptors {
+            interceptors: self.interceptors.clone(),
+            ctx: RequestContext {
+                operation,
+                org_id: project.org_id,
+                project_id: project.project_id,
+            },
+        }
+    }
 
-    let config = config.clone().unwrap();
+    // Turn an SDK error into our own error type. Errors produced by the Root gateway itself
+    // (rather than by the S3 backend behind it) get their own variants so callers can tell a
+    // bad API key or a wrong org/project apart from a regular S3 failure.
+    fn map_sdk_error<E>(
+        &self,
+        err: SdkError<E, HttpResponse>,
+        project: impl Into<ProjectContext>,
+        wrap: impl FnOnce(Box<E>) -> Error,
+    ) -> Error
+    where
+        E: std::error::Error + Send + Sync + CreateUnhandledError + 'static,
+    {
+        let project = project.into();
 
-    // Add the api key to the headers
-    req.headers_mut().append("x-api-key", config.api_key);
+        // The request never got an answer, there is no service error to wrap
+        if matches!(
+            err,
+            SdkError::DispatchFailure(_) | SdkError::TimeoutError(_)
+        ) {
+            return Error::Connection(DisplayErrorContext(&err).to_string());
+        }
 
-    let req_uri = req.uri().to_string();
-    log::debug!("uri: {:?}", req_uri);
-    let parts = req_uri.split('?').collect::<Vec<_>>();
-    // From splitted req uri, get the base url
-    let base_url = parts[0].to_owned();
-    let (url, _) = base_url.rsplit_once('/').unwrap();
-    log::debug!("url: {:?}", url);
+        if let (Some(config), Some(response)) = (&self.config, err.raw_response()) {
+            let org_id = project.org_id.unwrap_or(config.org_id);
+            if let Some(root_err) = root_gateway_error(response, org_id, project.project_id) {
+                return root_err;
+            }
+        }
 
-    let uri_mut = req.uri_mut();
-    let original_path = uri_mut.path().to_owned();
-    log::debug!("uri_mut path: {:?}", uri_mut.path());
+        wrap(Box::new(err.into_service_error()))
+    }
+}
+
+// The Root gateway answers authentication and routing failures with a JSON body, while the
+// S3 backend answers with XML. A 401 is never sent by the S3 backend, so it is always an API key
+// problem. For 403 and 404 we need the JSON body to know the gateway produced it, which means
+// HEAD requests (no body) fall back to the generic S3 error.
+fn root_gateway_error(
+    response: &HttpResponse,
+    org_id: i32,
+    project_id: Option<i32>,
+) -> Option<Error> {
+    let status = response.status().as_u16();
+    if status == 401 {
+        return Some(Error::InvalidApiKey);
+    }
+
+    if status != 403 && status != 404 {
+        return None;
+    }
+
+    let body: serde_json::Value = serde_json::from_slice(response.body().bytes()?).ok()?;
+    let message = ["code", "error", "message"]
+        .iter()
+        .filter_map(|field| body.get(field).and_then(serde_json::Value::as_str))
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    log::debug!("root gateway error ({status}): {message:?}");
+
+    match (status, project_id) {
+        (403, Some(project_id)) => Some(Error::ForbiddenProject(project_id)),
+        (404, _) if message.contains("organisation") || message.contains("organization") => {
+            Some(Error::UnknownOrganisation(org_id))
+        }
+        (404, Some(project_id)) if message.contains("project") => {
+            Some(Error::UnknownProject(project_id))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_s3::primitives::SdkBody;
+    use aws_smithy_runtime_api::{box_error::BoxError, http::StatusCode};
+    use interceptor::{Interceptor, RequestContext};
+    use std::sync::Mutex;
+
+    // Records the uri and headers of requests right before they are sent, then aborts them
+    #[derive(Debug, Default, Clone)]
+    pub(crate) struct Recorder {
+        pub(crate) requests: Arc<Mutex<Vec<(String, http::HeaderMap)>>>,
+    }
+
+    impl Recorder {
+        pub(crate) fn uris(&self) -> Vec<String> {
+            let requests = self.requests.lock().unwrap();
+            requests.iter().map(|(uri, _)| uri.clone()).collect()
+        }
+    }
 
-    // Construct the path
-    let mut path = format!(
-        "/api/v1/organisations/{}/projects/{}/s3",
-        config.org_id,
-        project_id.unwrap()
-    );
+    impl Interceptor for Recorder {
+        fn modify_before_transmit(
+            &self,
+            req: &mut aws_smithy_runtime_api::http::Request,
+            _ctx: &RequestContext,
+        ) -> Result<(), BoxError> {
+            let headers = req
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        http::HeaderName::try_from(name).unwrap(),
+                        http::HeaderValue::try_from(value).unwrap(),
+                    )
+                })
+                .collect();
+            self.requests
+                .lock()
+                .unwrap()
+                .push((req.uri().to_owned(), headers));
+            Err("recorded".into())
+        }
+    }
+
+    #[test]
+    fn addressing_style_from_str() {
+        let cases = [
+            ("path", Some(AddressingStyle::Path)),
+            ("virtual-hosted", Some(AddressingStyle::VirtualHosted)),
+            ("virtual", Some(AddressingStyle::VirtualHosted)),
+            ("Path", None),
+            ("virtual_hosted", None),
+            ("", None),
+        ];
 
-    // If the original path contains more than just a slash, add it to the path
-    if original_path != *"/" {
-        path += &original_path;
+        for (s, expected) in cases {
+            match (s.parse::<AddressingStyle>(), expected) {
+                (Ok(style), Some(expected)) => assert_eq!(style, expected, "{s:?}"),
+                (Err(Error::InvalidAddressingStyle(value)), None) => assert_eq!(value, s),
+                (res, _) => panic!("{s:?}: {res:?}"),
+            }
+        }
     }
 
-    // Construct the new uri with the path and original url (url can contain bucketname)
-    let mut new_uri = format!("{url}{path}");
+    #[tokio::test]
+    async fn with_addressing_style_moves_the_bucket() {
+        let cases = [
+            (
+                AddressingStyle::Path,
+                "http://localhost:9000/api/v1/organisations/1/projects/2/s3/bucket/key",
+            ),
+            (
+                AddressingStyle::VirtualHosted,
+                "http://bucket.localhost:9000/api/v1/organisations/1/projects/2/s3/key",
+            ),
+        ];
+
+        for (style, expected) in cases {
+            let recorder = Recorder::default();
+            let client = Client::new("http://localhost:9000", "key", 1)
+                .unwrap()
+                .with_addressing_style(style)
+                .with_interceptor(recorder.clone());
 
-    // Put back query if there was one
-    if let Some(query) = req.uri_mut().query() {
-        new_uri += &format!("?{query}");
+            assert!(client.head_object("bucket", "key", Some(2)).await.is_err());
+            assert_eq!(recorder.uris(), [expected], "{style:?}");
+        }
     }
 
-    let _ = req.set_uri(new_uri);
+    #[tokio::test]
+    async fn clients_are_virtual_hosted_by_default() {
+        let credentials = S3Credentials {
+            access_key_id: "access".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+            expiration: None,
+            region: "eu".to_string(),
+        };
+        let cases = [
+            (
+                Client::new("http://localhost:9000", "key", 1).unwrap(),
+                "http://bucket.localhost:9000/api/v1/organisations/1/projects/2/s3/key",
+            ),
+            (
+                Client::new_from_s3_credentials("http://localhost:9000", credentials).unwrap(),
+                "http://bucket.localhost:9000/key",
+            ),
+        ];
 
-    log::debug!("req: {:?}", req);
+        for (client, expected) in cases {
+            let recorder = Recorder::default();
+            let client = client.with_interceptor(recorder.clone());
+
+            assert!(client.head_object("bucket", "key", Some(2)).await.is_err());
+            assert_eq!(recorder.uris(), [expected]);
+        }
+    }
+
+    #[test]
+    fn root_gateway_error_cases() {
+        // (status, body, project, expected error)
+        let cases = [
+            (401, "", Some(2), Some("InvalidApiKey")),
+            (
+                401,
+                r#"{"error":"invalid api key"}"#,
+                None,
+                Some("InvalidApiKey"),
+            ),
+            (403, "", Some(2), None),
+            (
+                403,
+                r#"{"error":"forbidden"}"#,
+                Some(2),
+                Some("ForbiddenProject(2)"),
+            ),
+            (403, r#"{"error":"forbidden"}"#, None, None),
+            (404, "", Some(2), None),
+            (
+                404,
+                r#"{"code":"NotFound","message":"Organisation not found"}"#,
+                Some(2),
+                Some("UnknownOrganisation(1)"),
+            ),
+            (
+                404,
+                r#"{"error":"organization does not exist"}"#,
+                None,
+                Some("UnknownOrganisation(1)"),
+            ),
+            (
+                404,
+                r#"{"error":"Project not found"}"#,
+                Some(2),
+                Some("UnknownProject(2)"),
+            ),
+            (404, r#"{"error":"Project not found"}"#, None, None),
+            // Bodies that are not from the gateway, like an S3 error of a missing key
+            (404, r#"{"error":"no such key"}"#, Some(2), None),
+            (404, "<Error><Code>NoSuchKey</Code></Error>", Some(2), None),
+            (404, r#"["project"]"#, Some(2), None),
+            (500, r#"{"error":"project"}"#, Some(2), None),
+        ];
+
+        for (status, body, project_id, expected) in cases {
+            let response =
+                HttpResponse::new(StatusCode::try_from(status).unwrap(), SdkBody::from(body));
+            let error = root_gateway_error(&response, 1, project_id).map(|e| format!("{e:?}"));
+            assert_eq!(
+                error.as_deref(),
+                expected,
+                "status: {status}, body: {body}, project: {project_id:?}"
+            );
+        }
+    }
 }
@@ -0,0 +1,256 @@
+use crate::Client;
+use aws_sdk_s3::primitives::{ByteStream, SdkBody};
+use bytes::Bytes;
+use http_body::{Body, Frame, SizeHint};
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::time::Sleep;
+
+// Size of the chunks a limited body is sent or received in, so that an upload goes out evenly
+// instead of in bursts of a whole part
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Token bucket limiting the bytes per second of the transfers sharing it.
+///
+/// The bucket holds at most one second worth of bytes. A transfer that takes more than is in
+/// the bucket waits until it is refilled, and the transfers after it wait behind it.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_second: u64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    // Negative when transfers are waiting for bytes
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new(bytes_per_second: u64) -> Self {
+        let bytes_per_second = bytes_per_second.max(1);
+        Self {
+            bytes_per_second,
+            bucket: Mutex::new(Bucket {
+                tokens: bytes_per_second as f64,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    #[must_use]
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second
+    }
+
+    /// Takes `bytes` from the bucket and returns how long to wait before transferring them.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn reserve(&self, bytes: u64) -> Duration {
+        let rate = self.bytes_per_second as f64;
+        let mut bucket = self
+            .bucket
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.refilled).as_secs_f64() * rate;
+        bucket.tokens = (bucket.tokens + refill).min(rate) - bytes as f64;
+        bucket.refilled = now;
+
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / rate)
+        }
+    }
+}
+
+/// Rate limits of a [`Client`], set with [`Client::with_rate_limit`] and
+/// [`Client::with_operation_rate_limit`]. Clones of the client share them.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimits {
+    /// Limit of all uploads and downloads together.
+    pub global: Option<Arc<RateLimiter>>,
+    /// Limits of the bodies of single operations, like `UploadPart` or `GetObject`.
+    pub operations: HashMap<String, Arc<RateLimiter>>,
+}
+
+impl RateLimits {
+    fn for_operation(&self, operation: &str) -> Vec<Arc<RateLimiter>> {
+        self.global
+            .iter()
+            .chain(self.operations.get(operation))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Client {
+    /// Limits all uploads and downloads of this client and its clones together to
+    /// `bytes_per_second`.
+    #[must_use]
+    pub fn with_rate_limit(mut self, bytes_per_second: u64) -> Self {
+        self.rate_limits.global = Some(Arc::new(RateLimiter::new(bytes_per_second)));
+        self
+    }
+
+    /// Limits the bodies of one operation to `bytes_per_second`, on top of the limit of
+    /// [`Client::with_rate_limit`]. Uploads are sent with `PutObject` and `UploadPart`,
+    /// downloads are received with `GetObject`.
+    #[must_use]
+    pub fn with_operation_rate_limit(mut self, operation: &str, bytes_per_second: u64) -> Self {
+        self.rate_limits.operations.insert(
+            operation.to_string(),
+            Arc::new(RateLimiter::new(bytes_per_second)),
+        );
+        self
+    }
+
+    // Body sent or received by `operation`, slowed down to the rate limits that apply to it
+    pub(crate) fn throttle(&self, operation: &str, body: ByteStream) -> ByteStream {
+        let limiters = self.rate_limits.for_operation(operation);
+        if limiters.is_empty() {
+            return body;
+        }
+
+        let body = body.into_inner();
+        // Keep bodies in memory retryable, the SDK rebuilds them for every attempt
+        let throttled = if body.try_clone().is_some() {
+            SdkBody::retryable(move || {
+                let body = body.try_clone().unwrap_or_else(SdkBody::taken);
+                SdkBody::from_body_1_x(Throttled::new(body, limiters.clone()))
+            })
+        } else {
+            SdkBody::from_body_1_x(Throttled::new(body, limiters))
+        };
+
+        ByteStream::new(throttled)
+    }
+}
+
+// Body passing on the data of `inner` in chunks, each after the limiters had room for it
+struct Throttled {
+    inner: SdkBody,
+    limiters: Vec<Arc<RateLimiter>>,
+    // Data received from `inner` that was not passed on yet
+    pending: Bytes,
+    // Chunk waiting for `delay`
+    ready: Option<Bytes>,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl Throttled {
+    fn new(inner: SdkBody, limiters: Vec<Arc<RateLimiter>>) -> Self {
+        Self {
+            inner,
+            limiters,
+            pending: Bytes::new(),
+            ready: None,
+            delay: None,
+        }
+    }
+}
+
+impl Body for Throttled {
+    type Data = Bytes;
+    type Error = aws_smithy_types::body::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        let this = &mut *self;
+        loop {
+            if let Some(delay) = &mut this.delay {
+                ready!(delay.as_mut().poll(cx));
+                this.delay = None;
+            }
+            if let Some(chunk) = this.ready.take() {
+                return Poll::Ready(Some(Ok(Frame::data(chunk))));
+            }
+
+            if !this.pending.is_empty() {
+                let chunk = this.pending.split_to(CHUNK_SIZE.min(this.pending.len()));
+                let len = chunk.len() as u64;
+                let wait = this
+                    .limiters
+                    .iter()
+                    .map(|limiter| limiter.reserve(len))
+                    .max()
+                    .unwrap_or_default();
+
+                this.ready = Some(chunk);
+                if !wait.is_zero() {
+                    this.delay = Some(Box::pin(tokio::time::sleep(wait)));
+                }
+                continue;
+            }
+
+            match ready!(Pin::new(&mut this.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => this.pending = data,
+                    // Trailers
+                    Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                },
+                other => return Poll::Ready(other),
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.ready.is_none() && self.pending.is_empty() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let buffered = (self.pending.len() + self.ready.as_ref().map_or(0, Bytes::len)) as u64;
+        let inner = Body::size_hint(&self.inner);
+        let mut hint = SizeHint::new();
+        hint.set_lower(inner.lower() + buffered);
+        if let Some(upper) = inner.upper() {
+            hint.set_upper(upper + buffered);
+        }
+        hint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserve_waits_once_the_bucket_is_empty() {
+        let limiter = RateLimiter::new(1000);
+
+        assert_eq!(limiter.reserve(600), Duration::ZERO);
+        assert_eq!(limiter.reserve(400), Duration::ZERO);
+        let wait = limiter.reserve(500);
+        assert!(wait > Duration::from_millis(450) && wait <= Duration::from_millis(500));
+        // The next transfer waits behind the previous one
+        let wait = limiter.reserve(500);
+        assert!(wait > Duration::from_millis(950) && wait <= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn throttled_body_passes_on_everything_in_chunks() {
+        let client = Client::new("http://localhost:9000", "key", 1)
+            .unwrap()
+            .with_operation_rate_limit("PutObject", u64::MAX);
+        let data = vec![7u8; 3 * CHUNK_SIZE + 5];
+
+        let body = client.throttle("PutObject", ByteStream::from(data.clone()));
+        assert_eq!(
+            body.size_hint(),
+            (data.len() as u64, Some(data.len() as u64))
+        );
+        assert_eq!(body.collect().await.unwrap().into_bytes(), data);
+    }
+}
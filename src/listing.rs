@@ -0,0 +1,126 @@
+use crate::{Client, Error, ProjectContext};
+use aws_sdk_s3::types::Object;
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+
+/// One level of a bucket, as returned by [`Client::list_objects_with_delimiter`].
+#[derive(Debug, Clone, Default)]
+pub struct Listing {
+    /// Objects directly under the prefix.
+    pub objects: Vec<Object>,
+    /// Prefixes of the "directories" under the prefix, ending in the delimiter.
+    pub common_prefixes: Vec<String>,
+}
+
+// Number of objects in a page of a listing, buffered while the consumer catches up
+const PAGE_SIZE: usize = 1000;
+
+impl Client {
+    /// Lists the objects and "directories" under a prefix, the keys are grouped on the first
+    /// `delimiter` after the prefix like folders.
+    ///
+    /// With delimiter `/` and prefix `photos/`, `photos/a.jpg` is returned as an object and
+    /// `photos/2024/b.jpg` as the common prefix `photos/2024/`. All pages are fetched.
+    pub async fn list_objects_with_delimiter(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        delimiter: &str,
+        project_id: Option<i32>,
+    ) -> Result<Listing, Error> {
+        let mut listing = Listing::default();
+        let mut continuation_token = None;
+
+        loop {
+            let page = self
+                .list_objects_page(
+                    bucket,
+                    prefix,
+                    Some(delimiter),
+                    continuation_token,
+                    project_id,
+                )
+                .await?;
+
+            listing.objects.extend(page.contents.unwrap_or_default());
+            listing.common_prefixes.extend(
+                page.common_prefixes
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|p| p.prefix),
+            );
+
+            continuation_token = page.next_continuation_token;
+            if !page.is_truncated.unwrap_or_default() || continuation_token.is_none() {
+                return Ok(listing);
+            }
+        }
+    }
+
+    // All objects under a prefix by key, to look up the other side of a sync
+    pub(crate) async fn list_objects_by_key(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        project: impl Into<ProjectContext>,
+    ) -> Result<HashMap<String, Object>, Error> {
+        let mut objects = HashMap::new();
+        let mut listing = self.list_objects_stream(bucket, prefix, project);
+
+        while let Some(object) = listing.next().await {
+            let object = object?;
+            if let Some(key) = object.key.clone() {
+                objects.insert(key, object);
+            }
+        }
+
+        Ok(objects)
+    }
+
+    /// Lists all objects under a prefix as a stream, requesting the next page while the
+    /// current one is consumed.
+    ///
+    /// The stream ends after the first error.
+    pub fn list_objects_stream(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        project: impl Into<ProjectContext>,
+    ) -> ReceiverStream<Result<Object, Error>> {
+        let (tx, rx) = mpsc::channel(PAGE_SIZE);
+        let client = self.clone();
+        let (bucket, prefix, project) = (bucket.to_string(), prefix.to_string(), project.into());
+
+        tokio::spawn(async move {
+            let mut continuation_token = None;
+
+            loop {
+                let page = match client
+                    .list_objects_page(&bucket, &prefix, None, continuation_token, project)
+                    .await
+                {
+                    Ok(page) => page,
+                    Err(e) => {
+                        let _ = tx.send(Err(e)).await;
+                        return;
+                    }
+                };
+
+                for object in page.contents.unwrap_or_default() {
+                    if tx.send(Ok(object)).await.is_err() {
+                        // The stream was dropped
+                        return;
+                    }
+                }
+
+                continuation_token = page.next_continuation_token;
+                if !page.is_truncated.unwrap_or_default() || continuation_token.is_none() {
+                    return;
+                }
+            }
+        });
+
+        ReceiverStream::new(rx)
+    }
+}
@@ -0,0 +1,517 @@
+use crate::{
+    bulk::Finished, sync::dir_prefix, Client, CopyMethod, CopyOptions, Error, FailedSync,
+    ObjectLocation, SyncCompare, SyncOptions,
+};
+use aws_sdk_s3::types::Object;
+use serde::Serialize;
+use std::collections::HashMap;
+use tokio_stream::StreamExt;
+
+/// What [`Client::mirror`] did, or would do with [`SyncOptions::dry_run`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MirrorReport {
+    pub dry_run: bool,
+    pub copied: Vec<MirroredObject>,
+    /// Keys of the deleted objects at the destination.
+    pub deleted: Vec<String>,
+    /// Number of objects that were already up to date at the destination.
+    pub unchanged: usize,
+    /// Objects that could not be copied or deleted, keyed by their source or destination key.
+    pub failed: Vec<FailedSync>,
+}
+
+/// An object copied by [`Client::mirror`].
+#[derive(Debug, Clone, Serialize)]
+pub struct MirroredObject {
+    pub source_key: String,
+    pub destination_key: String,
+    pub size: i64,
+    /// How the object was copied, `None` with [`SyncOptions::dry_run`].
+    pub method: Option<CopyMethod>,
+}
+
+/// Totals of a [`MirrorReport`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct MirrorSummary {
+    pub copied: usize,
+    pub server_side: usize,
+    pub streamed: usize,
+    /// Bytes of the copied objects.
+    pub bytes: i64,
+    pub unchanged: usize,
+    pub deleted: usize,
+    pub failed: usize,
+}
+
+impl MirrorReport {
+    #[must_use]
+    pub fn summary(&self) -> MirrorSummary {
+        let method = |method| {
+            self.copied
+                .iter()
+                .filter(|object| object.method == Some(method))
+                .count()
+        };
+
+        MirrorSummary {
+            copied: self.copied.len(),
+            server_side: method(CopyMethod::ServerSide),
+            streamed: method(CopyMethod::Streamed),
+            bytes: self.copied.iter().map(|object| object.size).sum(),
+            unchanged: self.unchanged,
+            deleted: self.deleted.len(),
+            failed: self.failed.len(),
+        }
+    }
+}
+
+impl std::fmt::Display for MirrorSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} copied ({} bytes, {} server-side, {} streamed), {} unchanged, {} deleted, {} failed",
+            self.copied,
+            self.bytes,
+            self.server_side,
+            self.streamed,
+            self.unchanged,
+            self.deleted,
+            self.failed
+        )
+    }
+}
+
+enum MirrorOutcome {
+    Copied(MirroredObject),
+    Deleted(String),
+}
+
+impl Client {
+    /// Replicates the objects under the prefix `source.key` to the prefix `destination.key`,
+    /// where the destination is reached with `target`, possibly a client for another Root
+    /// instance. At most `options.concurrency` objects are copied at a time.
+    ///
+    /// When both clients use the same url and API key, and the source and destination are in
+    /// the same project, the objects are copied server-side with [`Client::copy`], otherwise
+    /// they are downloaded with this client and uploaded with `target`, keeping their content
+    /// headers, metadata and tags. Clients with access keys on the same url are assumed to
+    /// share them, objects the server refuses to copy are streamed.
+    ///
+    /// Fails with [`Error::InvalidMirror`] when the source and destination prefixes overlap in
+    /// the same bucket, as the listing of the source would pick up the copies.
+    ///
+    /// Objects that are already at the destination with the same size, and the same `ETag` or a
+    /// later date (see [`SyncCompare`]), are skipped, so running the mirror again only copies
+    /// what changed.
+    /// With [`SyncOptions::delete`], objects under the destination prefix that are not at the
+    /// source are deleted. [`SyncOptions::put_options`] is not used.
+    pub async fn mirror(
+        &self,
+        source: &ObjectLocation,
+        target: &Client,
+        destination: &ObjectLocation,
+        options: &SyncOptions,
+    ) -> Result<MirrorReport, Error> {
+        let job = MirrorJob::new(self, target, source, destination);
+        let source_prefix = dir_prefix(&source.key);
+        let destination_prefix = dir_prefix(&destination.key);
+
+        // The listing of the source would pick up the copies
+        if job.same_project && overlap(source, &source_prefix, destination, &destination_prefix) {
+            return Err(Error::InvalidMirror(format!(
+                "{}/{source_prefix} and {}/{destination_prefix} overlap",
+                source.bucket, destination.bucket
+            )));
+        }
+
+        let mut existing = target
+            .list_objects_by_key(
+                &destination.bucket,
+                &destination_prefix,
+                destination.project,
+            )
+            .await?;
+
+        let mut report = MirrorReport {
+            dry_run: options.dry_run,
+            ..Default::default()
+        };
+        let mut runner = options.runner();
+        // Source keys of the copies and destination keys of the deletes running, by index
+        let mut running = HashMap::new();
+        let mut objects = self.list_objects_stream(&source.bucket, &source_prefix, source.project);
+
+        while let Some(object) = objects.next().await {
+            let object = object?;
+            let Some(source_key) = object.key.clone() else {
+                continue;
+            };
+            let relative = source_key
+                .strip_prefix(&source_prefix)
+                .unwrap_or(&source_key);
+            if !options.selects(relative) {
+                continue;
+            }
+            let destination_key = format!("{destination_prefix}{relative}");
+
+            if existing
+                .remove(&destination_key)
+                .is_some_and(|copy| up_to_date(&object, &copy, options.compare))
+            {
+                report.unchanged += 1;
+                continue;
+            }
+
+            let mirrored = MirroredObject {
+                source_key,
+                destination_key,
+                size: object.size().unwrap_or_default(),
+                method: None,
+            };
+            if options.dry_run {
+                report.copied.push(mirrored);
+                continue;
+            }
+
+            for finished in runner.wait_for_slot().await {
+                record_mirror(&mut report, &mut running, finished);
+            }
+
+            let key = mirrored.source_key.clone();
+            let index = runner.spawn(
+                format!("copy of {key}"),
+                (job.clone(), mirrored),
+                |(job, mirrored)| async move { job.copy(mirrored).await },
+            );
+            running.insert(index, key);
+        }
+
+        if options.delete {
+            let mut extras = existing
+                .into_keys()
+                .filter(|key| options.selects(key.strip_prefix(&destination_prefix).unwrap_or(key)))
+                .collect::<Vec<_>>();
+            extras.sort();
+
+            for key in extras {
+                if options.dry_run {
+                    report.deleted.push(key);
+                    continue;
+                }
+
+                for finished in runner.wait_for_slot().await {
+                    record_mirror(&mut report, &mut running, finished);
+                }
+
+                let index = runner.spawn(
+                    format!("delete of {key}"),
+                    (job.clone(), key.clone()),
+                    |(job, key)| async move { job.delete(key).await },
+                );
+                running.insert(index, key);
+            }
+        }
+
+        for finished in runner.finish().await {
+            record_mirror(&mut report, &mut running, finished);
+        }
+
+        report
+            .copied
+            .sort_by(|a, b| a.source_key.cmp(&b.source_key));
+        report.deleted.sort();
+        Ok(report)
+    }
+
+    // Whether `source` of this client and `destination` of `other` are in the same project of
+    // the same server
+    fn same_project_as(
+        &self,
+        source: &ObjectLocation,
+        other: &Client,
+        destination: &ObjectLocation,
+    ) -> bool {
+        self.endpoint_url.trim_end_matches('/') == other.endpoint_url.trim_end_matches('/')
+            && self.resolve_project(source.project) == other.resolve_project(destination.project)
+    }
+
+    // Whether the two clients use the same credentials
+    fn same_api_key(&self, other: &Client) -> bool {
+        let api_key = |client: &Client| client.config.as_ref().map(|config| config.api_key.clone());
+
+        api_key(self) == api_key(other)
+    }
+}
+
+// The clients and prefixes of a mirror, for its copies and deletes
+#[derive(Clone)]
+struct MirrorJob {
+    client: Client,
+    target: Client,
+    source: ObjectLocation,
+    destination: ObjectLocation,
+    // Source and destination are in the same project of the same server
+    same_project: bool,
+    // Objects are copied with `CopyObject`, which can only copy within a project
+    server_side: bool,
+}
+
+impl MirrorJob {
+    fn new(
+        client: &Client,
+        target: &Client,
+        source: &ObjectLocation,
+        destination: &ObjectLocation,
+    ) -> Self {
+        let same_project = client.same_project_as(source, target, destination);
+        Self {
+            same_project,
+            server_side: same_project && client.same_api_key(target),
+            client: client.clone(),
+            target: target.clone(),
+            source: source.clone(),
+            destination: destination.clone(),
+        }
+    }
+
+    // Copy an object to the destination
+    async fn copy(&self, mirrored: MirroredObject) -> Result<MirrorOutcome, Error> {
+        let from = ObjectLocation {
+            key: mirrored.source_key.clone(),
+            ..self.source.clone()
+        };
+        let to = ObjectLocation {
+            key: mirrored.destination_key.clone(),
+            ..self.destination.clone()
+        };
+
+        let res = if self.server_side {
+            match self.target.copy(&from, &to, CopyOptions::default()).await {
+                // The access keys of the clients differ after all
+                Err(e) if e.code() == Some("AccessDenied") => {
+                    self.client
+                        .streamed_copy(&self.target, &from, &to, CopyOptions::default())
+                        .await
+                }
+                res => res,
+            }
+        } else {
+            self.client
+                .streamed_copy(&self.target, &from, &to, CopyOptions::default())
+                .await
+        };
+
+        Ok(MirrorOutcome::Copied(MirroredObject {
+            method: Some(res?.method),
+            ..mirrored
+        }))
+    }
+
+    // Delete an object at the destination that is gone from the source
+    async fn delete(&self, key: String) -> Result<MirrorOutcome, Error> {
+        let destination = &self.destination;
+        self.target
+            .delete_object_in(&destination.bucket, &key, destination.project)
+            .await?;
+        Ok(MirrorOutcome::Deleted(key))
+    }
+}
+
+fn record_mirror(
+    report: &mut MirrorReport,
+    running: &mut HashMap<usize, String>,
+    finished: Finished<MirrorOutcome>,
+) {
+    let Some(key) = running.remove(&finished.index) else {
+        return;
+    };
+
+    match finished.result {
+        Ok(MirrorOutcome::Copied(object)) => report.copied.push(object),
+        Ok(MirrorOutcome::Deleted(key)) => report.deleted.push(key),
+        Err(e) => {
+            log::warn!("failed to mirror {key}: {e}");
+            report.failed.push(FailedSync {
+                key,
+                error: e.to_string(),
+            });
+        }
+    }
+}
+
+// Whether two prefixes in the same project share objects
+pub(crate) fn overlap(
+    source: &ObjectLocation,
+    source_prefix: &str,
+    destination: &ObjectLocation,
+    destination_prefix: &str,
+) -> bool {
+    source.bucket == destination.bucket
+        && (destination_prefix.starts_with(source_prefix)
+            || source_prefix.starts_with(destination_prefix))
+}
+
+// Whether the copy at the destination still matches the source. Copies get a new date, so with
+// `SizeAndTime` a copy at least as recent as the source is up to date.
+fn up_to_date(source: &Object, copy: &Object, compare: SyncCompare) -> bool {
+    if source.size() != copy.size() {
+        return false;
+    }
+
+    match compare {
+        SyncCompare::SizeAndTime => match (source.last_modified(), copy.last_modified()) {
+            (Some(source), Some(copy)) => copy.secs() >= source.secs(),
+            _ => false,
+        },
+        SyncCompare::Checksum => source.e_tag().is_some() && source.e_tag() == copy.e_tag(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProjectContext;
+    use aws_sdk_s3::primitives::DateTime;
+
+    fn object(size: i64, modified: i64, e_tag: &str) -> Object {
+        Object::builder()
+            .key("a")
+            .size(size)
+            .last_modified(DateTime::from_secs(modified))
+            .e_tag(e_tag)
+            .build()
+    }
+
+    #[test]
+    fn only_copies_within_a_project_are_server_side() {
+        let client = Client::new("http://localhost:9000", "key", 1).unwrap();
+        let location = |project| ObjectLocation::new("b", "logs", project);
+        let in_organisation = |org_id| location(ProjectContext::in_organisation(org_id, 2));
+
+        // (target, destination, same project, server-side)
+        let cases = [
+            (client.clone(), location(Some(2).into()), true, true),
+            (client.clone(), in_organisation(1), true, true),
+            (client.clone(), location(Some(3).into()), false, false),
+            (client.clone(), in_organisation(7), false, false),
+            (
+                Client::new("http://localhost:9000/", "other", 1).unwrap(),
+                location(Some(2).into()),
+                true,
+                false,
+            ),
+            (
+                Client::new("http://localhost:9000", "key", 7).unwrap(),
+                location(Some(2).into()),
+                false,
+                false,
+            ),
+            (
+                Client::new("http://other:9000", "key", 1).unwrap(),
+                location(Some(2).into()),
+                false,
+                false,
+            ),
+        ];
+
+        for (target, destination, same_project, server_side) in cases {
+            let job = MirrorJob::new(&client, &target, &location(Some(2).into()), &destination);
+            assert_eq!(
+                (job.same_project, job.server_side),
+                (same_project, server_side),
+                "{} {destination:?}",
+                target.endpoint_url
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn mirror_rejects_overlapping_prefixes_in_a_project() {
+        // Nothing listens there, mirrors that are not rejected fail to list the destination
+        let client = Client::new("http://127.0.0.1:1", "key", 1).unwrap();
+        let streaming = Client::new("http://127.0.0.1:1", "other", 1).unwrap();
+        let location = |key: &str, project: Option<i32>| ObjectLocation::new("b", key, project);
+
+        // (target, source, destination, rejected)
+        let cases = [
+            (
+                &client,
+                location("logs", Some(2)),
+                location("logs/copy", Some(2)),
+                true,
+            ),
+            (
+                &streaming,
+                location("logs", Some(2)),
+                location("logs/copy", Some(2)),
+                true,
+            ),
+            (
+                &streaming,
+                location("logs/", Some(2)),
+                location("", Some(2)),
+                true,
+            ),
+            (
+                &client,
+                location("logs", Some(2)),
+                location("logs-copy", Some(2)),
+                false,
+            ),
+            (
+                &client,
+                location("logs", Some(2)),
+                location("logs/copy", Some(3)),
+                false,
+            ),
+        ];
+
+        for (target, source, destination, rejected) in cases {
+            let res = client
+                .mirror(&source, target, &destination, &SyncOptions::default())
+                .await;
+            assert_eq!(
+                matches!(res, Err(Error::InvalidMirror(_))),
+                rejected,
+                "{source:?} to {destination:?}: {res:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn up_to_date_by_size_and_time_or_etag() {
+        let source = object(3, 100, "\"abc\"");
+
+        assert!(up_to_date(
+            &source,
+            &object(3, 100, "\"abc\""),
+            SyncCompare::SizeAndTime
+        ));
+        assert!(up_to_date(
+            &source,
+            &object(3, 200, "\"def\""),
+            SyncCompare::SizeAndTime
+        ));
+        assert!(!up_to_date(
+            &source,
+            &object(3, 99, "\"abc\""),
+            SyncCompare::SizeAndTime
+        ));
+        assert!(!up_to_date(
+            &source,
+            &object(4, 200, "\"abc\""),
+            SyncCompare::SizeAndTime
+        ));
+
+        assert!(up_to_date(
+            &source,
+            &object(3, 50, "\"abc\""),
+            SyncCompare::Checksum
+        ));
+        assert!(!up_to_date(
+            &source,
+            &object(3, 200, "\"def\""),
+            SyncCompare::Checksum
+        ));
+    }
+}
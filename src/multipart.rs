@@ -0,0 +1,231 @@
+use crate::{options::encode_tagging, Client, Error, ObjectLocation, ProjectContext, PutOptions};
+use aws_sdk_s3::{
+    operation::complete_multipart_upload::CompleteMultipartUploadOutput,
+    types::{CompletedMultipartUpload, CompletedPart, Part},
+};
+
+/// Maximum number of parts in a multipart upload.
+pub const MAX_PARTS: i64 = 10_000;
+
+/// Minimum size of every part but the last one of a multipart upload.
+pub const MIN_PART_SIZE: i64 = 5 * 1024 * 1024;
+
+// Building blocks for multipart uploads and copies
+impl Client {
+    pub(crate) async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        project: ProjectContext,
+        options: PutOptions,
+    ) -> Result<String, Error> {
+        let res = self
+            .s3_client
+            .create_multipart_upload()
+            .key(key)
+            .bucket(bucket)
+            .set_content_type(options.content_type)
+            .set_cache_control(options.cache_control)
+            .set_content_disposition(options.content_disposition)
+            .set_content_encoding(options.content_encoding)
+            .set_storage_class(options.storage_class)
+            .set_metadata(options.metadata)
+            .set_tagging(options.tagging.as_ref().map(encode_tagging))
+            .customize()
+            .interceptor(
+                self.interceptors_for("CreateMultipartUpload", project)
+                    .with_headers(&options.headers),
+            )
+            .send()
+            .await
+            .map_err(|e| self.map_sdk_error(e, project, Error::ErrCreateMultipartUpload))?;
+
+        res.upload_id.ok_or(Error::MissingUploadId)
+    }
+
+    pub(crate) async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        project: ProjectContext,
+        upload_id: &str,
+        part_number: i32,
+        data: bytes::Bytes,
+    ) -> Result<CompletedPart, Error> {
+        let content_length = i64::try_from(data.len()).ok();
+
+        let res = self
+            .s3_client
+            .upload_part()
+            .key(key)
+            .bucket(bucket)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(self.throttle("UploadPart", data.into()))
+            .set_content_length(content_length)
+            .customize()
+            .interceptor(self.interceptors_for("UploadPart", project))
+            .send()
+            .await
+            .map_err(|e| self.map_sdk_error(e, project, Error::ErrUploadPart))?;
+
+        Ok(CompletedPart::builder()
+            .part_number(part_number)
+            .set_e_tag(res.e_tag)
+            .build())
+    }
+
+    // Copy the bytes `first..=last` of the source into a part of the upload
+    pub(crate) async fn upload_part_copy(
+        &self,
+        source: &ObjectLocation,
+        destination: &ObjectLocation,
+        upload_id: &str,
+        part_number: i32,
+        (first, last): (i64, i64),
+    ) -> Result<CompletedPart, Error> {
+        let res = self
+            .s3_client
+            .upload_part_copy()
+            .key(&destination.key)
+            .bucket(&destination.bucket)
+            .copy_source(crate::copy::copy_source(&source.bucket, &source.key))
+            .copy_source_range(format!("bytes={first}-{last}"))
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .customize()
+            .interceptor(self.interceptors_for("UploadPartCopy", destination.project))
+            .send()
+            .await
+            .map_err(|e| self.map_sdk_error(e, destination.project, Error::ErrUploadPartCopy))?;
+
+        Ok(CompletedPart::builder()
+            .part_number(part_number)
+            .set_e_tag(res.copy_part_result.and_then(|r| r.e_tag))
+            .build())
+    }
+
+    pub(crate) async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        project: ProjectContext,
+        upload_id: &str,
+        mut parts: Vec<CompletedPart>,
+    ) -> Result<CompleteMultipartUploadOutput, Error> {
+        parts.sort_by_key(|part| part.part_number);
+
+        let res = self
+            .s3_client
+            .complete_multipart_upload()
+            .key(key)
+            .bucket(bucket)
+            .upload_id(upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .customize()
+            .interceptor(self.interceptors_for("CompleteMultipartUpload", project))
+            .send()
+            .await
+            .map_err(|e| self.map_sdk_error(e, project, Error::ErrCompleteMultipartUpload))?;
+
+        Ok(res)
+    }
+
+    // Parts uploaded so far, following the pages of the listing
+    pub(crate) async fn list_parts(
+        &self,
+        destination: &ObjectLocation,
+        upload_id: &str,
+    ) -> Result<Vec<Part>, Error> {
+        let mut parts = Vec::new();
+        let mut marker = None;
+
+        loop {
+            let res = self
+                .s3_client
+                .list_parts()
+                .key(&destination.key)
+                .bucket(&destination.bucket)
+                .upload_id(upload_id)
+                .set_part_number_marker(marker)
+                .customize()
+                .interceptor(self.interceptors_for("ListParts", destination.project))
+                .send()
+                .await
+                .map_err(|e| self.map_sdk_error(e, destination.project, Error::ErrListParts))?;
+
+            parts.extend(res.parts.unwrap_or_default());
+            marker = res.next_part_number_marker;
+            if res.is_truncated != Some(true) || marker.is_none() {
+                return Ok(parts);
+            }
+        }
+    }
+
+    pub(crate) async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        project: ProjectContext,
+        upload_id: &str,
+    ) -> Result<(), Error> {
+        self.s3_client
+            .abort_multipart_upload()
+            .key(key)
+            .bucket(bucket)
+            .upload_id(upload_id)
+            .customize()
+            .interceptor(self.interceptors_for("AbortMultipartUpload", project))
+            .send()
+            .await
+            .map_err(|e| self.map_sdk_error(e, project, Error::ErrAbortMultipartUpload))?;
+
+        Ok(())
+    }
+}
+
+/// Size of the parts to split an object of `size` bytes in, at least `preferred` but large
+/// enough to stay within [`MAX_PARTS`].
+#[must_use]
+pub fn part_size(size: i64, preferred: i64) -> i64 {
+    preferred
+        .max(MIN_PART_SIZE)
+        .max((size + MAX_PARTS - 1) / MAX_PARTS)
+}
+
+// Inclusive byte ranges of the parts of an object of `size` bytes
+pub(crate) fn part_ranges(size: i64, part_size: i64) -> Vec<(i64, i64)> {
+    (0..size)
+        .step_by(usize::try_from(part_size).unwrap_or(usize::MAX))
+        .map(|first| (first, (first + part_size).min(size) - 1))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn part_size_stays_within_max_parts() {
+        const MIB: i64 = 1024 * 1024;
+
+        assert_eq!(part_size(100 * MIB, 8 * MIB), 8 * MIB);
+        assert_eq!(part_size(100 * MIB, MIB), MIN_PART_SIZE);
+        // 5 TiB in 256 MiB parts would be 20480 parts
+        let size = 5 * 1024 * 1024 * MIB;
+        assert_eq!(part_size(size, 256 * MIB), size / MAX_PARTS + 1);
+        assert!(part_ranges(size, part_size(size, 256 * MIB)).len() <= 10_000);
+    }
+
+    #[test]
+    fn part_ranges_cover_the_object() {
+        assert_eq!(part_ranges(0, 5), vec![]);
+        assert_eq!(part_ranges(5, 5), vec![(0, 4)]);
+        assert_eq!(part_ranges(12, 5), vec![(0, 4), (5, 9), (10, 11)]);
+        assert_eq!(part_ranges(3, 5), vec![(0, 2)]);
+    }
+}
@@ -0,0 +1,232 @@
+use aws_sdk_s3::types::{MetadataDirective, StorageClass, TaggingDirective};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::collections::HashMap;
+
+// Characters that are kept as-is in a query string
+const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Options for [`Client::put_object`](crate::Client::put_object).
+///
+/// All fields are optional, `PutOptions::default()` uploads the object without any extra headers.
+#[derive(Debug, Clone, Default)]
+pub struct PutOptions {
+    /// `Content-Type` of the object, e.g. `application/json`.
+    pub content_type: Option<String>,
+    /// `Cache-Control` returned when the object is downloaded.
+    pub cache_control: Option<String>,
+    /// `Content-Disposition` returned when the object is downloaded.
+    pub content_disposition: Option<String>,
+    /// `Content-Encoding` of the object, e.g. `gzip`.
+    pub content_encoding: Option<String>,
+    /// Storage class of the object.
+    pub storage_class: Option<StorageClass>,
+    /// User metadata, stored as `x-amz-meta-*` headers.
+    pub metadata: Option<HashMap<String, String>>,
+    /// Tags of the object.
+    pub tagging: Option<HashMap<String, String>>,
+    /// Any other headers to send with the request.
+    pub headers: Vec<(String, String)>,
+}
+
+/// Options for [`Client::get_object`](crate::Client::get_object).
+#[derive(Debug, Clone, Default)]
+pub struct GetOptions {
+    /// Only download part of the object, e.g. `bytes=0-1023`.
+    pub range: Option<String>,
+    /// Only return the object if its `ETag` matches.
+    pub if_match: Option<String>,
+    /// Only return the object if its `ETag` does not match.
+    pub if_none_match: Option<String>,
+    /// Any other headers to send with the request.
+    pub headers: Vec<(String, String)>,
+}
+
+/// Options for [`Client::copy_object`](crate::Client::copy_object).
+#[derive(Debug, Clone, Default)]
+pub struct CopyOptions {
+    /// `Content-Type` of the copy.
+    pub content_type: Option<String>,
+    /// `Cache-Control` of the copy.
+    pub cache_control: Option<String>,
+    /// `Content-Disposition` of the copy.
+    pub content_disposition: Option<String>,
+    /// `Content-Encoding` of the copy.
+    pub content_encoding: Option<String>,
+    /// Storage class of the copy.
+    pub storage_class: Option<StorageClass>,
+    /// User metadata of the copy.
+    pub metadata: Option<HashMap<String, String>>,
+    /// Whether the copy keeps the metadata and content headers of the source (`COPY`) or gets
+    /// the ones from these options (`REPLACE`). When not set, they are replaced if any of them
+    /// is given and copied otherwise.
+    pub metadata_directive: Option<MetadataDirective>,
+    /// Tags of the copy.
+    pub tagging: Option<HashMap<String, String>>,
+    /// Whether the copy keeps the tags of the source (`COPY`) or gets `tagging` (`REPLACE`).
+    /// When not set, tags are replaced if `tagging` is given and copied otherwise.
+    pub tagging_directive: Option<TaggingDirective>,
+    /// Any other headers to send with the request.
+    pub headers: Vec<(String, String)>,
+}
+
+impl CopyOptions {
+    // Metadata directive to send, S3 ignores new metadata and content headers unless it is
+    // told to replace them
+    pub(crate) fn resolved_metadata_directive(&self) -> MetadataDirective {
+        let replace = self.content_type.is_some()
+            || self.cache_control.is_some()
+            || self.content_disposition.is_some()
+            || self.content_encoding.is_some()
+            || self.metadata.is_some();
+
+        match &self.metadata_directive {
+            Some(directive) => directive.clone(),
+            None if replace => MetadataDirective::Replace,
+            None => MetadataDirective::Copy,
+        }
+    }
+
+    pub(crate) fn resolved_tagging_directive(&self) -> TaggingDirective {
+        match &self.tagging_directive {
+            Some(directive) => directive.clone(),
+            None if self.tagging.is_some() => TaggingDirective::Replace,
+            None => TaggingDirective::Copy,
+        }
+    }
+}
+
+// Tags as sent in the `x-amz-tagging` header, a url encoded query string
+pub(crate) fn encode_tagging(tags: &HashMap<String, String>) -> String {
+    let mut tags = tags.iter().collect::<Vec<_>>();
+    tags.sort();
+
+    tags.iter()
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(key, QUERY_VALUE),
+                utf8_percent_encode(value, QUERY_VALUE)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directives_copy_unless_something_is_replaced() {
+        let options = CopyOptions::default();
+        assert_eq!(
+            options.resolved_metadata_directive(),
+            MetadataDirective::Copy
+        );
+        assert_eq!(options.resolved_tagging_directive(), TaggingDirective::Copy);
+
+        let replacing = [
+            CopyOptions {
+                content_type: Some("text/plain".to_string()),
+                ..Default::default()
+            },
+            CopyOptions {
+                cache_control: Some("no-cache".to_string()),
+                ..Default::default()
+            },
+            CopyOptions {
+                content_disposition: Some("inline".to_string()),
+                ..Default::default()
+            },
+            CopyOptions {
+                content_encoding: Some("gzip".to_string()),
+                ..Default::default()
+            },
+            CopyOptions {
+                metadata: Some(HashMap::new()),
+                ..Default::default()
+            },
+        ];
+        for options in replacing {
+            assert_eq!(
+                options.resolved_metadata_directive(),
+                MetadataDirective::Replace,
+                "{options:?}"
+            );
+            assert_eq!(options.resolved_tagging_directive(), TaggingDirective::Copy);
+        }
+
+        let options = CopyOptions {
+            tagging: Some(HashMap::from([("a".to_string(), "b".to_string())])),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.resolved_metadata_directive(),
+            MetadataDirective::Copy
+        );
+        assert_eq!(
+            options.resolved_tagging_directive(),
+            TaggingDirective::Replace
+        );
+    }
+
+    #[test]
+    fn given_directives_are_kept() {
+        let options = CopyOptions {
+            content_type: Some("text/plain".to_string()),
+            metadata_directive: Some(MetadataDirective::Copy),
+            tagging: Some(HashMap::new()),
+            tagging_directive: Some(TaggingDirective::Copy),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.resolved_metadata_directive(),
+            MetadataDirective::Copy
+        );
+        assert_eq!(options.resolved_tagging_directive(), TaggingDirective::Copy);
+
+        let options = CopyOptions {
+            metadata_directive: Some(MetadataDirective::Replace),
+            tagging_directive: Some(TaggingDirective::Replace),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.resolved_metadata_directive(),
+            MetadataDirective::Replace
+        );
+        assert_eq!(
+            options.resolved_tagging_directive(),
+            TaggingDirective::Replace
+        );
+    }
+
+    #[test]
+    fn encode_tagging_cases() {
+        let cases = [
+            (vec![], ""),
+            (vec![("env", "prod")], "env=prod"),
+            // Sorted by key
+            (
+                vec![("team", "data"), ("env", "prod")],
+                "env=prod&team=data",
+            ),
+            (vec![("a&b", "c=d")], "a%26b=c%3Dd"),
+            (vec![("my tag", "two words")], "my%20tag=two%20words"),
+            (vec![("path", "a/b+c")], "path=a%2Fb%2Bc"),
+            (vec![("keep", "-_.~")], "keep=-_.~"),
+            (vec![("café", "ü")], "caf%C3%A9=%C3%BC"),
+        ];
+
+        for (tags, expected) in cases {
+            let tags = tags
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            assert_eq!(encode_tagging(&tags), expected);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// State of a transfer, passed to a [`ProgressObserver`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Bytes transferred so far.
+    pub transferred: u64,
+    /// Bytes to transfer, `None` when the length is not known up front, like for stdin.
+    pub total: Option<u64>,
+    /// Time since the transfer started.
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Average number of bytes per second since the start of the transfer.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn rate(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.transferred as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Estimated time left at the current [`rate`](Self::rate), `None` when the total is not
+    /// known or nothing was transferred yet.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn eta(&self) -> Option<Duration> {
+        let total = self.total?;
+        let rate = self.rate();
+        if rate <= 0.0 {
+            return None;
+        }
+
+        Some(Duration::from_secs_f64(
+            total.saturating_sub(self.transferred) as f64 / rate,
+        ))
+    }
+}
+
+/// Gets notified of the progress of an upload or download, e.g. to draw a progress bar.
+///
+/// Implemented for closures, so `Arc::new(|progress: &Progress| ...)` is an observer.
+pub trait ProgressObserver: Send + Sync {
+    /// Called every time more bytes were transferred.
+    fn progress(&self, progress: &Progress);
+}
+
+impl<F> ProgressObserver for F
+where
+    F: Fn(&Progress) + Send + Sync,
+{
+    fn progress(&self, progress: &Progress) {
+        self(progress);
+    }
+}
+
+/// Reader that reports the bytes read from `inner` to a [`ProgressObserver`].
+///
+/// Used by [`Client::upload_file`](crate::Client::upload_file) and
+/// [`Client::download_file`](crate::Client::download_file), and can wrap the reader passed to
+/// [`Client::upload_reader`](crate::Client::upload_reader) or the body of
+/// [`Client::get_object`](crate::Client::get_object).
+pub struct ProgressReader<R> {
+    inner: R,
+    observer: Arc<dyn ProgressObserver>,
+    transferred: u64,
+    total: Option<u64>,
+    started: Instant,
+}
+
+impl<R> ProgressReader<R> {
+    pub fn new(inner: R, total: Option<u64>, observer: Arc<dyn ProgressObserver>) -> Self {
+        Self {
+            inner,
+            observer,
+            transferred: 0,
+            total,
+            started: Instant::now(),
+        }
+    }
+
+    /// Counts from `transferred` bytes on, for a transfer that continues an earlier one.
+    #[must_use]
+    pub fn with_transferred(mut self, transferred: u64) -> Self {
+        self.transferred = transferred;
+        self
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ProgressReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        let read = buf.filled().len() - before;
+        if read > 0 {
+            self.transferred += read as u64;
+            self.observer.progress(&Progress {
+                transferred: self.transferred,
+                total: self.total,
+                elapsed: self.started.elapsed(),
+            });
+        }
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn rate_and_eta() {
+        let progress = Progress {
+            transferred: 300,
+            total: Some(1000),
+            elapsed: Duration::from_secs(3),
+        };
+        assert!((progress.rate() - 100.0).abs() < f64::EPSILON);
+        assert_eq!(progress.eta(), Some(Duration::from_secs(7)));
+
+        let unknown = Progress {
+            total: None,
+            ..progress
+        };
+        assert_eq!(unknown.eta(), None);
+
+        let started = Progress {
+            transferred: 0,
+            elapsed: Duration::ZERO,
+            ..progress
+        };
+        assert_eq!(started.eta(), None);
+    }
+
+    #[tokio::test]
+    async fn reader_reports_every_read() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let observer = {
+            let seen = seen.clone();
+            move |progress: &Progress| seen.lock().unwrap().push(progress.transferred)
+        };
+        let mut reader = ProgressReader::new(&b"abcdefg"[..], Some(7), Arc::new(observer));
+
+        let mut buf = [0; 3];
+        while reader.read(&mut buf).await.unwrap() > 0 {}
+
+        assert_eq!(*seen.lock().unwrap(), vec![3, 6, 7]);
+    }
+}
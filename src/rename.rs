@@ -0,0 +1,354 @@
+use crate::{
+    bulk::{Finished, Runner},
+    mirror::overlap,
+    sync::dir_prefix,
+    BulkOptions, Client, CopyOptions, CopyOutcome, Error, ObjectLocation, ProjectContext,
+};
+use aws_sdk_s3::operation::head_object::HeadObjectOutput;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio_stream::StreamExt;
+
+/// Progress of [`Client::move_prefix`], which can be saved and passed to a later run to
+/// continue an interrupted move.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MoveReport {
+    /// Prefix the objects are moved from, set by the first run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<ObjectLocation>,
+    /// Prefix the objects are moved to, set by the first run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<ObjectLocation>,
+    /// Objects that were moved.
+    pub moved: Vec<MovedObject>,
+    /// Objects that could not be moved in the last run.
+    pub failed: Vec<FailedMove>,
+}
+
+/// An object moved by [`Client::move_prefix`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MovedObject {
+    pub source_key: String,
+    pub destination_key: String,
+}
+
+/// An object [`Client::move_prefix`] could not move, it is still at the source.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedMove {
+    pub source_key: String,
+    pub error: String,
+}
+
+impl Client {
+    /// Moves an object to another location, possibly in another project.
+    ///
+    /// The object is copied with [`Client::copy`], after which the size of the copy, and its
+    /// `ETag` when neither side is a multipart upload, is checked against the source. Only then
+    /// is the source deleted, so a failed move leaves the source in place.
+    pub async fn move_object(
+        &self,
+        source: &ObjectLocation,
+        destination: &ObjectLocation,
+        options: CopyOptions,
+    ) -> Result<CopyOutcome, Error> {
+        if source == destination {
+            return Err(Error::InvalidMove(format!(
+                "{}/{} is both the source and the destination",
+                source.bucket, source.key
+            )));
+        }
+
+        let head = self
+            .head_object_in(&source.bucket, &source.key, source.project)
+            .await?;
+        let source_size = head.content_length;
+        let source_e_tag = head.e_tag.clone();
+
+        let outcome = self
+            .copy_with_head(source, destination, head, options)
+            .await?;
+
+        let copy = self
+            .head_object_in(&destination.bucket, &destination.key, destination.project)
+            .await?;
+        verify_copy(source_size, source_e_tag.as_deref(), &copy)
+            .map_err(|reason| Error::MoveVerificationFailed(destination.key.clone(), reason))?;
+
+        self.delete_object_in(&source.bucket, &source.key, source.project)
+            .await?;
+
+        Ok(outcome)
+    }
+
+    /// Moves all objects under the prefix `source.key` to the prefix `destination.key`,
+    /// moving at most `concurrency` objects at a time.
+    ///
+    /// Prefixes are directories: a `/` is added to a prefix that does not end with one, so
+    /// `logs` moves `logs/a` to `{destination}/a` but leaves `logs-old/a` alone. Moves failing
+    /// with a [retryable](Error::is_retryable) error are tried again like with
+    /// [`Client::run_bulk`].
+    ///
+    /// Objects that fail to move are recorded in `report` and left at the source, the other
+    /// objects are still moved. Passing the report of an earlier run continues it: the objects
+    /// it moved are no longer under the source prefix, the failed ones are retried, and the
+    /// report ends up covering both runs. A report of a move of other prefixes is refused with
+    /// [`Error::InvalidMove`]. Only listing the source fails the whole move.
+    pub async fn move_prefix(
+        &self,
+        source: &ObjectLocation,
+        destination: &ObjectLocation,
+        concurrency: usize,
+        report: &mut MoveReport,
+    ) -> Result<(), Error> {
+        let source_prefix = dir_prefix(&source.key);
+        let destination_prefix = dir_prefix(&destination.key);
+
+        // The listing would pick up the moved objects again
+        if self.same_project(source.project, destination.project)
+            && overlap(source, &source_prefix, destination, &destination_prefix)
+        {
+            return Err(Error::InvalidMove(format!(
+                "{}/{source_prefix} and {}/{destination_prefix} overlap",
+                source.bucket, destination.bucket
+            )));
+        }
+
+        // A report of another move would count objects that were never moved
+        let prefixes = (
+            ObjectLocation {
+                key: source_prefix.clone(),
+                ..source.clone()
+            },
+            ObjectLocation {
+                key: destination_prefix.clone(),
+                ..destination.clone()
+            },
+        );
+        let same = |a: &ObjectLocation, b: &ObjectLocation| {
+            a.bucket == b.bucket && a.key == b.key && self.same_project(a.project, b.project)
+        };
+        match (&report.source, &report.destination) {
+            (Some(from), Some(to)) if !same(from, &prefixes.0) || !same(to, &prefixes.1) => {
+                return Err(Error::InvalidMove(format!(
+                    "the report is of the move of {} to {}, not of {} to {}",
+                    describe(from),
+                    describe(to),
+                    describe(&prefixes.0),
+                    describe(&prefixes.1)
+                )));
+            }
+            _ => (report.source, report.destination) = (Some(prefixes.0), Some(prefixes.1)),
+        }
+        report.failed.clear();
+
+        let options = BulkOptions {
+            concurrency,
+            ..Default::default()
+        };
+        let mut runner = Runner::new(&options);
+        // Source and destination key of the moves that are running, by index
+        let mut running = HashMap::new();
+        let mut objects = self.list_objects_stream(&source.bucket, &source_prefix, source.project);
+
+        while let Some(object) = objects.next().await {
+            let Some(source_key) = object?.key else {
+                continue;
+            };
+
+            for finished in runner.wait_for_slot().await {
+                record_move(report, &mut running, finished);
+            }
+
+            let destination_key = format!(
+                "{destination_prefix}{}",
+                source_key
+                    .strip_prefix(&source_prefix)
+                    .unwrap_or(&source_key)
+            );
+            let from = ObjectLocation::new(&source.bucket, &source_key, source.project);
+            let to =
+                ObjectLocation::new(&destination.bucket, &destination_key, destination.project);
+            let index = runner.spawn(
+                format!("move of {source_key}"),
+                (self.clone(), from, to),
+                |(client, from, to)| async move {
+                    client.move_object(&from, &to, CopyOptions::default()).await
+                },
+            );
+            running.insert(index, (source_key, destination_key));
+        }
+
+        for finished in runner.finish().await {
+            record_move(report, &mut running, finished);
+        }
+
+        Ok(())
+    }
+}
+
+fn record_move(
+    report: &mut MoveReport,
+    running: &mut HashMap<usize, (String, String)>,
+    finished: Finished<CopyOutcome>,
+) {
+    let Some((source_key, destination_key)) = running.remove(&finished.index) else {
+        return;
+    };
+
+    match finished.result {
+        Ok(_) => report.moved.push(MovedObject {
+            source_key,
+            destination_key,
+        }),
+        Err(e) => {
+            log::warn!("failed to move {source_key}: {e}");
+            report.failed.push(FailedMove {
+                source_key,
+                error: e.to_string(),
+            });
+        }
+    }
+}
+
+fn describe(location: &ObjectLocation) -> String {
+    let ProjectContext { org_id, project_id } = location.project;
+    let project = match (org_id, project_id) {
+        (Some(org_id), Some(project_id)) => {
+            format!(" in project {project_id} of organisation {org_id}")
+        }
+        (None, Some(project_id)) => format!(" in project {project_id}"),
+        _ => String::new(),
+    };
+    format!("{}/{}{project}", location.bucket, location.key)
+}
+
+// Compare the copy against the source, multipart `ETag`s depend on the part size so they are
+// only compared when neither object is a multipart upload
+fn verify_copy(
+    source_size: Option<i64>,
+    source_e_tag: Option<&str>,
+    copy: &HeadObjectOutput,
+) -> Result<(), String> {
+    if source_size != copy.content_length {
+        return Err(format!(
+            "size {} instead of {}",
+            copy.content_length.unwrap_or_default(),
+            source_size.unwrap_or_default()
+        ));
+    }
+
+    if let (Some(source), Some(copy)) = (source_e_tag, copy.e_tag.as_deref()) {
+        if !source.contains('-') && !copy.contains('-') && source != copy {
+            return Err(format!("etag {copy} instead of {source}"));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn head(size: i64, e_tag: &str) -> HeadObjectOutput {
+        HeadObjectOutput::builder()
+            .content_length(size)
+            .e_tag(e_tag)
+            .build()
+    }
+
+    #[test]
+    fn verify_copy_compares_size_and_single_part_etags() {
+        assert!(verify_copy(Some(3), Some("\"abc\""), &head(3, "\"abc\"")).is_ok());
+        assert!(verify_copy(Some(4), Some("\"abc\""), &head(3, "\"abc\"")).is_err());
+        assert!(verify_copy(Some(3), Some("\"abc\""), &head(3, "\"def\"")).is_err());
+        // Multipart etags differ with the part size
+        assert!(verify_copy(Some(3), Some("\"abc-2\""), &head(3, "\"def-3\"")).is_ok());
+        assert!(verify_copy(Some(3), Some("\"abc\""), &head(3, "\"def-3\"")).is_ok());
+    }
+
+    #[tokio::test]
+    async fn move_prefix_rejects_overlapping_directories() {
+        // Nothing listens there, moves that are not rejected fail to list the source
+        let client = Client::new("http://127.0.0.1:1", "key", 1).unwrap();
+        let location = |bucket: &str, key: &str| ObjectLocation::new(bucket, key, Some(2));
+
+        // (source, destination, rejected)
+        let cases = [
+            (location("b", "logs"), location("b", "logs/archive/"), true),
+            (location("b", "logs/"), location("b", "logs"), true),
+            (location("b", "logs/2024/"), location("b", "logs/"), true),
+            (location("b", ""), location("b", "archive/"), true),
+            (location("b", "logs"), location("b", "logs-archive/"), false),
+            (location("b", "logs-archive"), location("b", "logs"), false),
+            (location("b", "logs"), location("other", "logs"), false),
+            (
+                location("b", "logs"),
+                ObjectLocation::new("b", "logs/archive", ProjectContext::in_organisation(1, 2)),
+                true,
+            ),
+            (
+                location("b", "logs"),
+                ObjectLocation::new("b", "logs", Some(3)),
+                false,
+            ),
+        ];
+
+        for (source, destination, rejected) in cases {
+            let res = client
+                .move_prefix(&source, &destination, 1, &mut MoveReport::default())
+                .await;
+            assert_eq!(
+                matches!(res, Err(Error::InvalidMove(_))),
+                rejected,
+                "{source:?} to {destination:?}: {res:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn move_prefix_refuses_the_report_of_another_move() {
+        // Nothing listens there, moves that are not refused fail to list the source
+        let client = Client::new("http://127.0.0.1:1", "key", 1).unwrap();
+        let location = |bucket: &str, key: &str| ObjectLocation::new(bucket, key, Some(2));
+        let (source, destination) = (location("b", "logs"), location("b", "archive/"));
+
+        // A new report takes the prefixes of its first run
+        let mut report = MoveReport::default();
+        let res = client
+            .move_prefix(&source, &destination, 1, &mut report)
+            .await;
+        assert!(matches!(res, Err(Error::Connection(_))), "{res:?}");
+        assert_eq!(report.source, Some(location("b", "logs/")));
+        assert_eq!(report.destination, Some(location("b", "archive/")));
+
+        // (source, destination, refused)
+        let cases = [
+            (location("b", "logs/"), location("b", "archive"), false),
+            (
+                location("b", "logs"),
+                ObjectLocation::new("b", "archive", ProjectContext::in_organisation(1, 2)),
+                false,
+            ),
+            (location("b", "logs/2024"), location("b", "archive/"), true),
+            (location("b", "logs"), location("other", "archive/"), true),
+            (
+                location("b", "logs"),
+                ObjectLocation::new("b", "archive", Some(3)),
+                true,
+            ),
+        ];
+
+        for (source, destination, refused) in cases {
+            let mut report = report.clone();
+            let res = client
+                .move_prefix(&source, &destination, 1, &mut report)
+                .await;
+            assert_eq!(
+                matches!(res, Err(Error::InvalidMove(_))),
+                refused,
+                "{source:?} to {destination:?}: {res:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,637 @@
+use crate::{
+    multipart::MAX_PARTS,
+    progress::Progress,
+    upload::{upload_part_ranges, upload_part_size, UPLOAD_CONCURRENCY},
+    Client, Error, ObjectLocation, ProgressObserver, PutOptions, UploadOutcome,
+};
+use aws_sdk_s3::types::{CompletedPart, Part};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt},
+    task::JoinSet,
+};
+
+/// A multipart upload of a file that can be continued after it failed, saved by
+/// [`Client::upload_file_resumable`] after every part.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadCheckpoint {
+    /// Url of the client that started the upload.
+    pub endpoint_url: String,
+    /// Organisation of the destination, the one of the client that started the upload when
+    /// the destination has none.
+    #[serde(default)]
+    pub org_id: Option<i32>,
+    pub destination: ObjectLocation,
+    /// Absolute path of the uploaded file.
+    pub path: PathBuf,
+    /// Size of the file when the upload started.
+    pub file_size: u64,
+    /// Modification time of the file when the upload started, in seconds since the epoch.
+    pub file_modified: u64,
+    pub upload_id: String,
+    /// Size of the first parts, see [`upload_part_size`](crate::upload::upload_part_size).
+    pub part_size: i64,
+    /// Parts the server received.
+    pub parts: Vec<CheckpointPart>,
+    /// When the upload started, in seconds since the epoch.
+    pub started: u64,
+}
+
+/// An uploaded part of an [`UploadCheckpoint`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckpointPart {
+    pub part_number: i32,
+    pub e_tag: String,
+    pub size: i64,
+}
+
+impl UploadCheckpoint {
+    /// Bytes of the file the server received.
+    #[must_use]
+    pub fn uploaded(&self) -> i64 {
+        self.parts.iter().map(|part| part.size).sum()
+    }
+
+    /// Whether the file still has the size and modification time it had when the upload
+    /// started. A file that changed or is gone can not be resumed.
+    #[must_use]
+    pub fn matches_file(&self) -> bool {
+        std::fs::metadata(&self.path).is_ok_and(|meta| {
+            meta.len() == self.file_size && modified_secs(&meta) == self.file_modified
+        })
+    }
+}
+
+/// Directory holding the checkpoints of resumable uploads, one JSON file per upload.
+#[derive(Debug, Clone)]
+pub struct Checkpoints {
+    dir: PathBuf,
+}
+
+impl Checkpoints {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// All checkpoints, oldest first. Files that are not checkpoints are skipped.
+    pub fn list(&self) -> Result<Vec<UploadCheckpoint>, Error> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(Error::LocalFile(self.dir.display().to_string(), e)),
+        };
+
+        let mut checkpoints = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|path| read_checkpoint(&path))
+            .collect::<Vec<_>>();
+        checkpoints.sort_by_key(|checkpoint| checkpoint.started);
+
+        Ok(checkpoints)
+    }
+
+    /// Checkpoint of the upload of the file at `path` to `destination` with `client`, if there
+    /// is one.
+    #[must_use]
+    pub fn find(
+        &self,
+        client: &Client,
+        destination: &ObjectLocation,
+        path: &Path,
+    ) -> Option<UploadCheckpoint> {
+        let (org_id, _) = client.resolve_project(destination.project);
+        read_checkpoint(&self.file_for(&client.endpoint_url, org_id, destination, path))
+    }
+
+    /// Writes the checkpoint, replacing the previous one of the same upload at once so that
+    /// a crash never leaves half a checkpoint behind.
+    pub fn save(&self, checkpoint: &UploadCheckpoint) -> Result<(), Error> {
+        let io_error = |path: &Path| {
+            let path = path.display().to_string();
+            move |e| Error::LocalFile(path, e)
+        };
+
+        std::fs::create_dir_all(&self.dir).map_err(io_error(&self.dir))?;
+        let file = self.file_of(checkpoint);
+        let partial = file.with_extension("json.part");
+        let content = serde_json::to_vec_pretty(checkpoint)
+            .map_err(|e| Error::LocalFile(partial.display().to_string(), e.into()))?;
+
+        std::fs::write(&partial, content).map_err(io_error(&partial))?;
+        std::fs::rename(&partial, &file).map_err(io_error(&file))
+    }
+
+    pub fn remove(&self, checkpoint: &UploadCheckpoint) -> Result<(), Error> {
+        let file = self.file_of(checkpoint);
+        match std::fs::remove_file(&file) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                Err(Error::LocalFile(file.display().to_string(), e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn file_of(&self, checkpoint: &UploadCheckpoint) -> PathBuf {
+        self.file_for(
+            &checkpoint.endpoint_url,
+            checkpoint.org_id,
+            &checkpoint.destination,
+            &checkpoint.path,
+        )
+    }
+
+    // Checkpoints are named after what is uploaded where, so a rerun of the same upload finds
+    // its checkpoint
+    fn file_for(
+        &self,
+        endpoint_url: &str,
+        org_id: Option<i32>,
+        destination: &ObjectLocation,
+        path: &Path,
+    ) -> PathBuf {
+        let mut hasher = Md5::new();
+        for field in [
+            endpoint_url.trim_end_matches('/'),
+            &format!("{org_id:?}"),
+            &format!("{:?}", destination.project),
+            &destination.bucket,
+            &destination.key,
+            &path.display().to_string(),
+        ] {
+            hasher.update(field.as_bytes());
+            hasher.update([0]);
+        }
+
+        self.dir
+            .join(format!("{}.json", hex::encode(hasher.finalize())))
+    }
+}
+
+impl Client {
+    /// Uploads the file at `path` like [`Client::upload_file`], but a failed multipart upload
+    /// is not aborted: its upload id and parts are kept in a checkpoint in `checkpoints`, and
+    /// uploading the same file to the same destination again only uploads the missing parts.
+    ///
+    /// A checkpoint of a file that changed since is discarded and the upload starts over.
+    /// Files smaller than a part are uploaded at once, without a checkpoint.
+    ///
+    /// The failed upload is never aborted here: its parts stay on the server, taking up
+    /// space, until the upload is continued or [`Client::discard_upload`] aborts it. Use
+    /// [`Client::upload_file`] to abort failed uploads instead.
+    pub async fn upload_file_resumable(
+        &self,
+        path: &Path,
+        destination: &ObjectLocation,
+        options: PutOptions,
+        checkpoints: &Checkpoints,
+        progress: Option<Arc<dyn ProgressObserver>>,
+    ) -> Result<UploadOutcome, Error> {
+        let io_error = |e| Error::LocalFile(path.display().to_string(), e);
+        let path = std::fs::canonicalize(path).map_err(io_error)?;
+        let meta = std::fs::metadata(&path).map_err(io_error)?;
+        if i64::try_from(meta.len()).unwrap_or(i64::MAX) < upload_part_size(1) {
+            return self
+                .upload_file(&path, destination, options, progress)
+                .await;
+        }
+
+        if let Some(checkpoint) = checkpoints.find(self, destination, &path) {
+            if checkpoint.matches_file() {
+                match self.list_parts(destination, &checkpoint.upload_id).await {
+                    Ok(parts) => {
+                        log::info!(
+                            "resuming upload {} of {}",
+                            checkpoint.upload_id,
+                            path.display()
+                        );
+                        return self
+                            .continue_upload(checkpoint, &parts, checkpoints, progress)
+                            .await;
+                    }
+                    // Aborted, completed or cleaned up by the server, start over
+                    Err(e) if e.code() == Some("NoSuchUpload") => {
+                        checkpoints.remove(&checkpoint)?;
+                    }
+                    Err(e) => return Err(e),
+                }
+            } else if let Err(e) = self.discard_upload(&checkpoint, checkpoints).await {
+                log::warn!(
+                    "failed to discard the upload of the previous {}: {e}",
+                    path.display()
+                );
+            }
+        }
+
+        let upload_id = self
+            .create_multipart_upload(
+                &destination.bucket,
+                &destination.key,
+                destination.project,
+                options,
+            )
+            .await?;
+        let checkpoint = UploadCheckpoint {
+            endpoint_url: self.endpoint_url.clone(),
+            org_id: self.resolve_project(destination.project).0,
+            destination: destination.clone(),
+            path,
+            file_size: meta.len(),
+            file_modified: modified_secs(&meta),
+            upload_id,
+            part_size: upload_part_size(1),
+            parts: Vec::new(),
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+        };
+        checkpoints.save(&checkpoint)?;
+
+        self.continue_upload(checkpoint, &[], checkpoints, progress)
+            .await
+    }
+
+    /// Continues the upload of a checkpoint, e.g. one of [`Checkpoints::list`], with the
+    /// parts the server does not have yet, of the size the upload started with. Fails with
+    /// [`Error::StaleCheckpoint`] when the file changed, which [`Client::discard_upload`] cleans
+    /// up, or when the server no longer knows the upload, which removes the checkpoint, and
+    /// with [`Error::ForeignCheckpoint`] when the upload was started with another url or
+    /// organisation.
+    pub async fn resume_upload(
+        &self,
+        checkpoint: UploadCheckpoint,
+        checkpoints: &Checkpoints,
+        progress: Option<Arc<dyn ProgressObserver>>,
+    ) -> Result<UploadOutcome, Error> {
+        let stale = |reason: &str| {
+            Error::StaleCheckpoint(checkpoint.path.display().to_string(), reason.to_string())
+        };
+        self.check_checkpoint(&checkpoint)?;
+        if !checkpoint.matches_file() {
+            return Err(stale("the file changed or is gone"));
+        }
+
+        let parts = match self
+            .list_parts(&checkpoint.destination, &checkpoint.upload_id)
+            .await
+        {
+            Ok(parts) => parts,
+            // Nothing left to resume or clean up
+            Err(e) if e.code() == Some("NoSuchUpload") => {
+                checkpoints.remove(&checkpoint)?;
+                return Err(stale("the server no longer has the upload"));
+            }
+            Err(e) => return Err(e),
+        };
+
+        self.continue_upload(checkpoint, &parts, checkpoints, progress)
+            .await
+    }
+
+    /// Aborts the upload of a checkpoint and removes the checkpoint.
+    pub async fn discard_upload(
+        &self,
+        checkpoint: &UploadCheckpoint,
+        checkpoints: &Checkpoints,
+    ) -> Result<(), Error> {
+        self.check_checkpoint(checkpoint)?;
+        let destination = &checkpoint.destination;
+        match self
+            .abort_multipart_upload(
+                &destination.bucket,
+                &destination.key,
+                destination.project,
+                &checkpoint.upload_id,
+            )
+            .await
+        {
+            Err(e) if e.code() != Some("NoSuchUpload") => return Err(e),
+            _ => {}
+        }
+
+        checkpoints.remove(checkpoint)
+    }
+
+    /// Whether the upload of `checkpoint` was started with the url and organisation of this
+    /// client, which it can only be continued or discarded with.
+    #[must_use]
+    pub fn started_upload(&self, checkpoint: &UploadCheckpoint) -> bool {
+        self.check_checkpoint(checkpoint).is_ok()
+    }
+
+    // Refuse a checkpoint of another server or organisation, its upload id means nothing there
+    fn check_checkpoint(&self, checkpoint: &UploadCheckpoint) -> Result<(), Error> {
+        let foreign = |reason: String| {
+            Err(Error::ForeignCheckpoint(
+                checkpoint.path.display().to_string(),
+                reason,
+            ))
+        };
+        if self.endpoint_url.trim_end_matches('/') != checkpoint.endpoint_url.trim_end_matches('/')
+        {
+            return foreign(format!("at {}", checkpoint.endpoint_url));
+        }
+        let (org_id, _) = self.resolve_project(checkpoint.destination.project);
+        if org_id != checkpoint.org_id {
+            return foreign(match checkpoint.org_id {
+                Some(org_id) => format!("in organisation {org_id}"),
+                None => "without an organisation".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    // Upload the parts that are not among `uploaded` and complete the upload, saving the
+    // checkpoint after every part
+    async fn continue_upload(
+        &self,
+        mut checkpoint: UploadCheckpoint,
+        uploaded: &[Part],
+        checkpoints: &Checkpoints,
+        progress: Option<Arc<dyn ProgressObserver>>,
+    ) -> Result<UploadOutcome, Error> {
+        let size = i64::try_from(checkpoint.file_size).unwrap_or(i64::MAX);
+        if checkpoint.part_size <= 0 {
+            return Err(Error::StaleCheckpoint(
+                checkpoint.path.display().to_string(),
+                format!("invalid part size {}", checkpoint.part_size),
+            ));
+        }
+        // The parts keep the size the upload started with
+        let ranges = upload_part_ranges(size, checkpoint.part_size);
+        if i64::try_from(ranges.len()).unwrap_or(i64::MAX) > MAX_PARTS {
+            return Err(Error::TooManyParts(MAX_PARTS));
+        }
+
+        // Only parts that were saved and that the server has with the same ETag count
+        checkpoint.parts.retain(|part| {
+            uploaded.iter().any(|p| {
+                p.part_number == Some(part.part_number) && p.e_tag.as_deref() == Some(&part.e_tag)
+            })
+        });
+        checkpoints.save(&checkpoint)?;
+
+        let started = Instant::now();
+        let report = |checkpoint: &UploadCheckpoint| {
+            if let Some(observer) = &progress {
+                observer.progress(&Progress {
+                    transferred: u64::try_from(checkpoint.uploaded()).unwrap_or_default(),
+                    total: Some(checkpoint.file_size),
+                    elapsed: started.elapsed(),
+                });
+            }
+        };
+        report(&checkpoint);
+
+        let mut tasks = JoinSet::new();
+        let missing = ranges.into_iter().filter(|(part_number, ..)| {
+            !checkpoint
+                .parts
+                .iter()
+                .any(|part| part.part_number == *part_number)
+        });
+        for range in missing.collect::<Vec<_>>() {
+            while tasks.len() >= UPLOAD_CONCURRENCY {
+                if let Some(res) = tasks.join_next().await {
+                    let part = res.map_err(|e| Error::TaskFailed(e.to_string()))??;
+                    record_part(&mut checkpoint, checkpoints, part);
+                    report(&checkpoint);
+                }
+            }
+
+            let client = self.clone();
+            let (path, destination) = (checkpoint.path.clone(), checkpoint.destination.clone());
+            let upload_id = checkpoint.upload_id.clone();
+            tasks.spawn(async move {
+                client
+                    .upload_file_part(&path, &destination, &upload_id, range)
+                    .await
+            });
+        }
+
+        while let Some(res) = tasks.join_next().await {
+            let part = res.map_err(|e| Error::TaskFailed(e.to_string()))??;
+            record_part(&mut checkpoint, checkpoints, part);
+            report(&checkpoint);
+        }
+
+        let parts = checkpoint
+            .parts
+            .iter()
+            .map(|part| {
+                CompletedPart::builder()
+                    .part_number(part.part_number)
+                    .e_tag(&part.e_tag)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        let count = parts.len();
+        let destination = &checkpoint.destination;
+        let res = self
+            .complete_multipart_upload(
+                &destination.bucket,
+                &destination.key,
+                destination.project,
+                &checkpoint.upload_id,
+                parts,
+            )
+            .await?;
+        checkpoints.remove(&checkpoint)?;
+
+        Ok(UploadOutcome {
+            e_tag: res.e_tag,
+            size,
+            parts: count,
+        })
+    }
+
+    // Read the bytes `first..=last` of the file and upload them as a part
+    async fn upload_file_part(
+        &self,
+        path: &Path,
+        destination: &ObjectLocation,
+        upload_id: &str,
+        (part_number, first, last): (i32, i64, i64),
+    ) -> Result<CheckpointPart, Error> {
+        let io_error = |e| Error::LocalFile(path.display().to_string(), e);
+        let size = last - first + 1;
+
+        let mut file = tokio::fs::File::open(path).await.map_err(io_error)?;
+        file.seek(std::io::SeekFrom::Start(
+            u64::try_from(first).unwrap_or_default(),
+        ))
+        .await
+        .map_err(io_error)?;
+        let mut data = vec![0; usize::try_from(size).unwrap_or_default()];
+        file.read_exact(&mut data).await.map_err(io_error)?;
+
+        let part = self
+            .upload_part(
+                &destination.bucket,
+                &destination.key,
+                destination.project,
+                upload_id,
+                part_number,
+                data.into(),
+            )
+            .await?;
+
+        Ok(CheckpointPart {
+            part_number,
+            e_tag: part.e_tag.unwrap_or_default(),
+            size,
+        })
+    }
+}
+
+// Keep a finished part, a checkpoint that can not be saved only costs uploading the part again
+fn record_part(checkpoint: &mut UploadCheckpoint, checkpoints: &Checkpoints, part: CheckpointPart) {
+    checkpoint.parts.push(part);
+    checkpoint.parts.sort_by_key(|part| part.part_number);
+    if let Err(e) = checkpoints.save(checkpoint) {
+        log::warn!(
+            "failed to save the checkpoint of {}: {e}",
+            checkpoint.path.display()
+        );
+    }
+}
+
+fn read_checkpoint(path: &Path) -> Option<UploadCheckpoint> {
+    let content = std::fs::read(path).ok()?;
+    serde_json::from_slice(&content)
+        .inspect_err(|e| log::warn!("ignoring checkpoint {}: {e}", path.display()))
+        .ok()
+}
+
+fn modified_secs(meta: &std::fs::Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProjectContext;
+
+    fn checkpoint(path: &Path) -> UploadCheckpoint {
+        UploadCheckpoint {
+            endpoint_url: "http://localhost:9000".to_string(),
+            org_id: Some(1),
+            destination: ObjectLocation::new("bucket", "a.bin", Some(1)),
+            path: path.to_path_buf(),
+            file_size: 3,
+            file_modified: 0,
+            upload_id: "upload".to_string(),
+            part_size: upload_part_size(1),
+            parts: vec![CheckpointPart {
+                part_number: 1,
+                e_tag: "\"abc\"".to_string(),
+                size: 3,
+            }],
+            started: 0,
+        }
+    }
+
+    #[test]
+    fn checkpoints_are_saved_found_and_removed() {
+        let dir = std::env::temp_dir().join(format!("root-s3-checkpoints-{}", std::process::id()));
+        let checkpoints = Checkpoints::new(&dir);
+        let saved = checkpoint(Path::new("/data/a.bin"));
+
+        assert!(checkpoints.list().unwrap().is_empty());
+        checkpoints.save(&saved).unwrap();
+        checkpoints.save(&saved).unwrap();
+
+        assert_eq!(checkpoints.list().unwrap(), vec![saved.clone()]);
+        let client = Client::new("http://localhost:9000/", "key", 1).unwrap();
+        assert_eq!(
+            checkpoints.find(&client, &saved.destination, &saved.path),
+            Some(saved.clone())
+        );
+        let other = ObjectLocation::new("bucket", "b.bin", Some(1));
+        assert_eq!(checkpoints.find(&client, &other, &saved.path), None);
+        // The same upload with another organisation is another upload
+        let other_organisation = Client::new("http://localhost:9000", "key", 2).unwrap();
+        assert_eq!(
+            checkpoints.find(&other_organisation, &saved.destination, &saved.path),
+            None
+        );
+        assert!(!saved.matches_file());
+
+        checkpoints.remove(&saved).unwrap();
+        assert!(checkpoints.list().unwrap().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn uploads_are_only_continued_where_they_started() {
+        let dir = std::env::temp_dir().join(format!("root-s3-foreign-{}", std::process::id()));
+        let checkpoints = Checkpoints::new(&dir);
+        // Nothing listens there, checkpoints that are not refused fail later
+        let saved = UploadCheckpoint {
+            endpoint_url: "http://127.0.0.1:1".to_string(),
+            ..checkpoint(Path::new("/nonexistent/root-s3/a.bin"))
+        };
+        let in_organisation_7 = UploadCheckpoint {
+            destination: ObjectLocation::new(
+                "bucket",
+                "a.bin",
+                ProjectContext::in_organisation(7, 1),
+            ),
+            org_id: Some(7),
+            ..saved.clone()
+        };
+
+        // (client, checkpoint, started there)
+        let cases = [
+            (Client::new("http://127.0.0.1:1/", "key", 1), &saved, true),
+            (Client::new("http://127.0.0.1:1", "other", 1), &saved, true),
+            (Client::new("http://127.0.0.1:2", "key", 1), &saved, false),
+            (Client::new("http://127.0.0.1:1", "key", 2), &saved, false),
+            (
+                Client::new("http://127.0.0.1:1", "key", 2),
+                &in_organisation_7,
+                true,
+            ),
+        ];
+
+        for (client, checkpoint, started) in cases {
+            let client = client.unwrap();
+            assert_eq!(
+                client.started_upload(checkpoint),
+                started,
+                "{}",
+                client.endpoint_url
+            );
+
+            let resumed = client
+                .resume_upload(checkpoint.clone(), &checkpoints, None)
+                .await;
+            let discarded = client.discard_upload(checkpoint, &checkpoints).await;
+            if started {
+                assert!(
+                    matches!(resumed, Err(Error::StaleCheckpoint(..))),
+                    "{resumed:?}"
+                );
+                assert!(!matches!(discarded, Err(Error::ForeignCheckpoint(..))));
+            } else {
+                assert!(
+                    matches!(resumed, Err(Error::ForeignCheckpoint(..))),
+                    "{resumed:?}"
+                );
+                assert!(matches!(discarded, Err(Error::ForeignCheckpoint(..))));
+            }
+        }
+    }
+}
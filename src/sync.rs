@@ -0,0 +1,685 @@
+use crate::{
+    bulk::{Finished, Runner},
+    download::is_partial,
+    filter::glob_to_regex,
+    upload::{upload_part_size, UPLOAD_PART_SIZE},
+    BulkOptions, Client, Error, ObjectLocation, ProjectContext, PutOptions,
+};
+use aws_sdk_s3::types::Object;
+use md5::{Digest, Md5};
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio_stream::StreamExt;
+
+/// How [`Client::sync_up`] and [`Client::sync_down`] decide that a file and its object differ.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncCompare {
+    /// The sizes differ, or the file was modified after the object was uploaded. Downloaded
+    /// files get the date of their object, any other date counts as a change.
+    #[default]
+    SizeAndTime,
+    /// The sizes or the MD5 checksums differ. Every file of the same size as its object is
+    /// read to compute the `ETag` it would get, which only matches objects uploaded by this
+    /// library or in a single part, other objects are always transferred.
+    Checksum,
+}
+
+/// Options of [`Client::sync_up`] and [`Client::sync_down`].
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    pub compare: SyncCompare,
+    /// Delete what is gone from the source: objects without a file when uploading, files
+    /// without an object when downloading.
+    pub delete: bool,
+    /// Only report what would be done.
+    pub dry_run: bool,
+    /// Number of files transferred or deleted at the same time. Transfers failing with a
+    /// [retryable](Error::is_retryable) error are tried again like with
+    /// [`Client::run_bulk`](crate::Client::run_bulk).
+    pub concurrency: usize,
+    /// Only sync paths, relative to the synced directory and prefix, matching one of these.
+    pub include: Vec<Regex>,
+    /// Leave paths matching one of these alone, also when deleting.
+    pub exclude: Vec<Regex>,
+    /// Headers, metadata and tags of the objects uploaded by [`Client::sync_up`].
+    pub put_options: PutOptions,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            compare: SyncCompare::default(),
+            delete: false,
+            dry_run: false,
+            concurrency: 8,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            put_options: PutOptions::default(),
+        }
+    }
+}
+
+impl SyncOptions {
+    /// Only sync paths matching a glob, where `*` and `?` do not match `/` and `**` matches
+    /// anything.
+    pub fn include(mut self, glob: &str) -> Result<Self, Error> {
+        self.include.push(glob_to_regex(glob)?);
+        Ok(self)
+    }
+
+    /// Leave paths matching a glob alone.
+    pub fn exclude(mut self, glob: &str) -> Result<Self, Error> {
+        self.exclude.push(glob_to_regex(glob)?);
+        Ok(self)
+    }
+
+    // Runs the transfers and deletes of a sync
+    pub(crate) fn runner<T: Send + 'static>(&self) -> Runner<T> {
+        Runner::new(&BulkOptions {
+            concurrency: self.concurrency,
+            ..Default::default()
+        })
+    }
+
+    // Whether a path relative to the synced directory is synced
+    pub(crate) fn selects(&self, relative: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|glob| glob.is_match(relative)))
+            && !self.exclude.iter().any(|glob| glob.is_match(relative))
+    }
+}
+
+/// What [`Client::sync_up`] or [`Client::sync_down`] did, or would do with
+/// [`SyncOptions::dry_run`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SyncReport {
+    pub dry_run: bool,
+    pub uploaded: Vec<SyncedFile>,
+    pub downloaded: Vec<SyncedFile>,
+    /// Keys of the deleted objects, or paths of the deleted files when downloading.
+    pub deleted: Vec<String>,
+    /// Number of files that did not change.
+    pub unchanged: usize,
+    pub failed: Vec<FailedSync>,
+}
+
+/// A file that was copied to or from the bucket.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncedFile {
+    pub path: PathBuf,
+    pub key: String,
+    pub size: i64,
+}
+
+/// A file or object that could not be synced, the others were still synced.
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedSync {
+    pub key: String,
+    pub error: String,
+}
+
+// A file under the synced directory
+#[derive(Debug, Clone)]
+struct LocalFile {
+    path: PathBuf,
+    // Path relative to the directory, with `/` separators
+    relative: String,
+    size: i64,
+    modified: SystemTime,
+}
+
+enum SyncOutcome {
+    Uploaded(SyncedFile),
+    Downloaded(SyncedFile),
+    Deleted(String),
+    Unchanged,
+}
+
+impl Client {
+    /// Uploads the files under `directory` that are new or changed to the objects under `prefix`,
+    /// at most `options.concurrency` at a time.
+    ///
+    /// A file `a/b.txt` is uploaded to `{prefix}/a/b.txt`, a `/` is added to a prefix that does
+    /// not end with one. With [`SyncOptions::delete`], objects under the prefix without a file
+    /// are deleted. Files that fail are recorded in the report and the others still synced, only
+    /// walking the directory or listing the prefix fails the whole sync.
+    pub async fn sync_up(
+        &self,
+        directory: &Path,
+        bucket: &str,
+        prefix: &str,
+        project: impl Into<ProjectContext>,
+        options: &SyncOptions,
+    ) -> Result<SyncReport, Error> {
+        let project = project.into();
+        let prefix = dir_prefix(prefix);
+
+        let root = directory.to_path_buf();
+        let files = tokio::task::spawn_blocking(move || local_files(&root))
+            .await
+            .map_err(|e| Error::TaskFailed(e.to_string()))??;
+
+        let mut objects = self.list_objects_by_key(bucket, &prefix, project).await?;
+
+        let mut report = SyncReport {
+            dry_run: options.dry_run,
+            ..Default::default()
+        };
+        let mut runner = options.runner();
+        // Keys of the transfers and deletes that are running, by index
+        let mut running = HashMap::new();
+
+        for file in files {
+            if !options.selects(&file.relative) {
+                continue;
+            }
+            let key = format!("{prefix}{}", file.relative);
+            let object = objects.remove(&key);
+
+            for finished in runner.wait_for_slot().await {
+                record_sync(&mut report, &mut running, finished);
+            }
+
+            let location = ObjectLocation::new(bucket, &key, project);
+            let index = runner.spawn(
+                format!("upload of {key}"),
+                (self.clone(), file, location, object, options.clone()),
+                |(client, file, location, object, options)| async move {
+                    client.sync_file(file, location, object, &options).await
+                },
+            );
+            running.insert(index, key);
+        }
+
+        if options.delete {
+            let mut extras = objects
+                .into_keys()
+                .filter(|key| options.selects(key.strip_prefix(&prefix).unwrap_or(key)))
+                .collect::<Vec<_>>();
+            extras.sort();
+
+            for key in extras {
+                if options.dry_run {
+                    report.deleted.push(key);
+                    continue;
+                }
+
+                for finished in runner.wait_for_slot().await {
+                    record_sync(&mut report, &mut running, finished);
+                }
+
+                let location = ObjectLocation::new(bucket, &key, project);
+                let index = runner.spawn(
+                    format!("delete of {key}"),
+                    (self.clone(), location),
+                    |(client, location)| async move {
+                        client
+                            .delete_object_in(&location.bucket, &location.key, location.project)
+                            .await?;
+                        Ok(SyncOutcome::Deleted(location.key))
+                    },
+                );
+                running.insert(index, key);
+            }
+        }
+
+        for finished in runner.finish().await {
+            record_sync(&mut report, &mut running, finished);
+        }
+
+        report.uploaded.sort_by(|a, b| a.key.cmp(&b.key));
+        report.deleted.sort();
+        Ok(report)
+    }
+
+    /// Downloads the objects under `prefix` that are new or changed to the files under
+    /// `directory`, at most `options.concurrency` at a time.
+    ///
+    /// The object `{prefix}/a/b.txt` is downloaded to `a/b.txt` and gets the modification time
+    /// of the object, files are written next to their destination and renamed into place once
+    /// complete. Keys that would end up outside of `directory`, like `../a` or `a//b`, are
+    /// recorded as failed and never written. With [`SyncOptions::delete`], files without an
+    /// object are deleted. Only listing the prefix or walking the directory fails the whole sync.
+    pub async fn sync_down(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        project: impl Into<ProjectContext>,
+        directory: &Path,
+        options: &SyncOptions,
+    ) -> Result<SyncReport, Error> {
+        let project = project.into();
+        let prefix = dir_prefix(prefix);
+
+        let root = directory.to_path_buf();
+        let files = if directory.exists() {
+            tokio::task::spawn_blocking(move || local_files(&root))
+                .await
+                .map_err(|e| Error::TaskFailed(e.to_string()))??
+        } else {
+            Vec::new()
+        };
+        let mut files = files
+            .into_iter()
+            .map(|file| (file.relative.clone(), file))
+            .collect::<HashMap<_, _>>();
+
+        let mut report = SyncReport {
+            dry_run: options.dry_run,
+            ..Default::default()
+        };
+        let mut runner = options.runner();
+        let mut running = HashMap::new();
+        let mut objects = self.list_objects_stream(bucket, &prefix, project);
+
+        while let Some(object) = objects.next().await {
+            let object = object?;
+            let Some(key) = object.key.clone() else {
+                continue;
+            };
+            let relative = key.strip_prefix(&prefix).unwrap_or(&key).to_string();
+            // Directory markers have no content to download
+            if relative.is_empty() || relative.ends_with('/') || !options.selects(&relative) {
+                continue;
+            }
+            let Some(path) = local_path(directory, &relative) else {
+                report.failed.push(FailedSync {
+                    error: Error::UnsafeKey(key.clone()).to_string(),
+                    key,
+                });
+                continue;
+            };
+            let file = files.remove(&relative);
+
+            for finished in runner.wait_for_slot().await {
+                record_sync(&mut report, &mut running, finished);
+            }
+
+            let location = ObjectLocation::new(bucket, &key, project);
+            let index = runner.spawn(
+                format!("download of {key}"),
+                (self.clone(), object, location, path, file, options.clone()),
+                |(client, object, location, path, file, options)| async move {
+                    client
+                        .sync_object(object, location, path, file, &options)
+                        .await
+                },
+            );
+            running.insert(index, key);
+        }
+
+        if options.delete {
+            // Partial files of failed downloads are kept so the next sync continues them
+            let mut extras = files
+                .into_values()
+                .filter(|file| options.selects(&file.relative) && !is_partial(&file.path))
+                .collect::<Vec<_>>();
+            extras.sort_by(|a, b| a.relative.cmp(&b.relative));
+
+            for file in extras {
+                let path = file.path.display().to_string();
+                if options.dry_run {
+                    report.deleted.push(path);
+                    continue;
+                }
+                match tokio::fs::remove_file(&file.path).await {
+                    Ok(()) => report.deleted.push(path),
+                    Err(e) => report.failed.push(FailedSync {
+                        key: format!("{prefix}{}", file.relative),
+                        error: Error::LocalFile(path, e).to_string(),
+                    }),
+                }
+            }
+        }
+
+        for finished in runner.finish().await {
+            record_sync(&mut report, &mut running, finished);
+        }
+
+        report.downloaded.sort_by(|a, b| a.key.cmp(&b.key));
+        report.deleted.sort();
+        Ok(report)
+    }
+
+    // Download an object when the file differs from it
+    async fn sync_object(
+        &self,
+        object: Object,
+        location: ObjectLocation,
+        path: PathBuf,
+        file: Option<LocalFile>,
+        options: &SyncOptions,
+    ) -> Result<SyncOutcome, Error> {
+        let changed = match (&file, options.compare) {
+            (None, _) => true,
+            (Some(file), SyncCompare::SizeAndTime) => outdated_by_size_and_time(file, &object),
+            (Some(file), SyncCompare::Checksum) => checksum_differs(file, &object).await?,
+        };
+
+        if !changed {
+            return Ok(SyncOutcome::Unchanged);
+        }
+
+        let synced = SyncedFile {
+            path,
+            key: location.key.clone(),
+            size: object.size().unwrap_or_default(),
+        };
+        if options.dry_run {
+            return Ok(SyncOutcome::Downloaded(synced));
+        }
+
+        let modified = object
+            .last_modified()
+            .and_then(|date| u64::try_from(date.secs()).ok())
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+        let outcome = self
+            .download(
+                &location,
+                &synced.path,
+                crate::GetOptions::default(),
+                None,
+                modified,
+            )
+            .await?;
+
+        Ok(SyncOutcome::Downloaded(SyncedFile {
+            size: outcome.size,
+            ..synced
+        }))
+    }
+
+    // Upload a file when it differs from its object
+    async fn sync_file(
+        &self,
+        file: LocalFile,
+        location: ObjectLocation,
+        object: Option<Object>,
+        options: &SyncOptions,
+    ) -> Result<SyncOutcome, Error> {
+        let changed = match (&object, options.compare) {
+            (None, _) => true,
+            (Some(object), SyncCompare::SizeAndTime) => changed_by_size_and_time(&file, object),
+            (Some(object), SyncCompare::Checksum) => checksum_differs(&file, object).await?,
+        };
+
+        if !changed {
+            return Ok(SyncOutcome::Unchanged);
+        }
+
+        let synced = SyncedFile {
+            path: file.path,
+            key: location.key.clone(),
+            size: file.size,
+        };
+        if options.dry_run {
+            return Ok(SyncOutcome::Uploaded(synced));
+        }
+
+        let outcome = self
+            .upload_file(&synced.path, &location, options.put_options.clone(), None)
+            .await?;
+
+        Ok(SyncOutcome::Uploaded(SyncedFile {
+            size: outcome.size,
+            ..synced
+        }))
+    }
+}
+
+fn record_sync(
+    report: &mut SyncReport,
+    running: &mut HashMap<usize, String>,
+    finished: Finished<SyncOutcome>,
+) {
+    let Some(key) = running.remove(&finished.index) else {
+        return;
+    };
+
+    match finished.result {
+        Ok(SyncOutcome::Uploaded(file)) => report.uploaded.push(file),
+        Ok(SyncOutcome::Downloaded(file)) => report.downloaded.push(file),
+        Ok(SyncOutcome::Deleted(key)) => report.deleted.push(key),
+        Ok(SyncOutcome::Unchanged) => report.unchanged += 1,
+        Err(e) => {
+            log::warn!("failed to sync {key}: {e}");
+            report.failed.push(FailedSync {
+                key,
+                error: e.to_string(),
+            });
+        }
+    }
+}
+
+// A prefix the relative paths can be appended to
+pub(crate) fn dir_prefix(prefix: &str) -> String {
+    if prefix.is_empty() || prefix.ends_with('/') {
+        prefix.to_string()
+    } else {
+        format!("{prefix}/")
+    }
+}
+
+// The object is older than the file, or of another size. Object dates only have seconds.
+fn changed_by_size_and_time(file: &LocalFile, object: &Object) -> bool {
+    object.size() != Some(file.size)
+        || object
+            .last_modified()
+            .is_none_or(|uploaded| modified_secs(file) > uploaded.secs())
+}
+
+// The file is not the download of the object, whose date `sync_down` gives the file
+fn outdated_by_size_and_time(file: &LocalFile, object: &Object) -> bool {
+    object.size() != Some(file.size)
+        || object
+            .last_modified()
+            .is_none_or(|uploaded| modified_secs(file) != uploaded.secs())
+}
+
+fn modified_secs(file: &LocalFile) -> i64 {
+    file.modified
+        .duration_since(UNIX_EPOCH)
+        .map(|since| i64::try_from(since.as_secs()).unwrap_or(i64::MAX))
+        .unwrap_or_default()
+}
+
+// Compare the MD5 `ETag` of the file with the one of the object, when the sizes match
+async fn checksum_differs(file: &LocalFile, object: &Object) -> Result<bool, Error> {
+    if object.size() != Some(file.size) {
+        return Ok(true);
+    }
+
+    let (path, size) = (file.path.clone(), file.size);
+    let e_tag = tokio::task::spawn_blocking(move || file_e_tag(&path, size))
+        .await
+        .map_err(|e| Error::TaskFailed(e.to_string()))?
+        .map_err(|e| Error::LocalFile(file.path.display().to_string(), e))?;
+
+    Ok(object.e_tag().map(|e_tag| e_tag.trim_matches('"')) != Some(e_tag.as_str()))
+}
+
+// Path under `directory` to download the object at `relative` to, `None` when the key would
+// end up outside of it (`..`, absolute paths) or does not name a file
+fn local_path(directory: &Path, relative: &str) -> Option<PathBuf> {
+    let mut path = directory.to_path_buf();
+
+    for segment in relative.split('/') {
+        let mut components = Path::new(segment).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(name)), None) if name == segment => path.push(name),
+            _ => return None,
+        }
+    }
+
+    Some(path)
+}
+
+// All files under `root`, sorted by path. Symbolic links to files are followed, links to
+// directories are not, so a link can not make the walk loop.
+fn local_files(root: &Path) -> Result<Vec<LocalFile>, Error> {
+    let io_error = |path: &Path| {
+        let path = path.display().to_string();
+        move |e| Error::LocalFile(path, e)
+    };
+
+    let mut files = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+
+    while let Some(directory) = directories.pop() {
+        for entry in std::fs::read_dir(&directory).map_err(io_error(&directory))? {
+            let entry = entry.map_err(io_error(&directory))?;
+            let path = entry.path();
+            let file_type = entry.file_type().map_err(io_error(&path))?;
+
+            if file_type.is_dir() {
+                directories.push(path);
+                continue;
+            }
+            let metadata = std::fs::metadata(&path).map_err(io_error(&path))?;
+            if !metadata.is_file() {
+                continue;
+            }
+
+            let relative = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push(LocalFile {
+                relative,
+                size: i64::try_from(metadata.len()).unwrap_or(i64::MAX),
+                modified: metadata.modified().map_err(io_error(&path))?,
+                path,
+            });
+        }
+    }
+
+    files.sort_by(|a, b| a.relative.cmp(&b.relative));
+    Ok(files)
+}
+
+// The `ETag` (without quotes) a file gets when uploaded with `Client::upload_reader`: the MD5
+// of the content for a single part, or the MD5 of the MD5s of the parts followed by the
+// number of parts
+fn file_e_tag(path: &Path, size: i64) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+
+    if size < UPLOAD_PART_SIZE {
+        let mut hasher = Md5::new();
+        std::io::copy(&mut file, &mut hasher)?;
+        return Ok(hex::encode(hasher.finalize()));
+    }
+
+    let mut digests = Md5::new();
+    let mut parts = 0;
+    for part_number in 1.. {
+        let mut hasher = Md5::new();
+        let part_size = u64::try_from(upload_part_size(part_number)).unwrap_or_default();
+        if std::io::copy(&mut (&mut file).take(part_size), &mut hasher)? == 0 {
+            break;
+        }
+        digests.update(hasher.finalize());
+        parts = part_number;
+    }
+
+    Ok(format!("{}-{parts}", hex::encode(digests.finalize())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_s3::primitives::DateTime;
+    use std::time::Duration;
+
+    fn file(size: i64, modified: u64) -> LocalFile {
+        LocalFile {
+            path: PathBuf::from("a.txt"),
+            relative: "a.txt".to_string(),
+            size,
+            modified: UNIX_EPOCH + Duration::from_millis(modified),
+        }
+    }
+
+    fn object(size: i64, uploaded: i64) -> Object {
+        Object::builder()
+            .key("a.txt")
+            .size(size)
+            .last_modified(DateTime::from_secs(uploaded))
+            .build()
+    }
+
+    #[test]
+    fn size_and_time_ignore_sub_second_differences() {
+        assert!(!changed_by_size_and_time(
+            &file(3, 100_000),
+            &object(3, 100)
+        ));
+        assert!(!changed_by_size_and_time(
+            &file(3, 100_900),
+            &object(3, 100)
+        ));
+        assert!(!changed_by_size_and_time(&file(3, 99_000), &object(3, 100)));
+        assert!(changed_by_size_and_time(&file(3, 101_000), &object(3, 100)));
+        assert!(changed_by_size_and_time(&file(4, 99_000), &object(3, 100)));
+    }
+
+    #[test]
+    fn include_and_exclude_globs() {
+        let options = SyncOptions::default()
+            .include("**.csv")
+            .unwrap()
+            .exclude("tmp/**")
+            .unwrap();
+
+        assert!(options.selects("a.csv"));
+        assert!(options.selects("data/2024/a.csv"));
+        assert!(!options.selects("a.json"));
+        assert!(!options.selects("tmp/a.csv"));
+        assert!(SyncOptions::default().selects("anything/at/all"));
+    }
+
+    #[test]
+    fn downloaded_files_keep_the_date_of_the_object() {
+        assert!(!outdated_by_size_and_time(
+            &file(3, 100_500),
+            &object(3, 100)
+        ));
+        assert!(outdated_by_size_and_time(&file(3, 99_000), &object(3, 100)));
+        assert!(outdated_by_size_and_time(
+            &file(3, 101_000),
+            &object(3, 100)
+        ));
+        assert!(outdated_by_size_and_time(
+            &file(4, 100_000),
+            &object(3, 100)
+        ));
+    }
+
+    #[test]
+    fn local_path_stays_in_the_directory() {
+        let directory = Path::new("out");
+
+        assert_eq!(
+            local_path(directory, "a/b.txt"),
+            Some(Path::new("out").join("a").join("b.txt"))
+        );
+        assert_eq!(local_path(directory, "..a"), Some(directory.join("..a")));
+        for key in ["../a", "a/../../b", "/etc/passwd", "a//b", "./a", "a/."] {
+            assert_eq!(local_path(directory, key), None, "{key}");
+        }
+    }
+
+    #[test]
+    fn dir_prefix_ends_with_a_slash() {
+        assert_eq!(dir_prefix(""), "");
+        assert_eq!(dir_prefix("backups"), "backups/");
+        assert_eq!(dir_prefix("backups/"), "backups/");
+    }
+}
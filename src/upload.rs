@@ -0,0 +1,299 @@
+use crate::{
+    multipart::MAX_PARTS, progress::ProgressReader, Client, Error, ObjectLocation,
+    ProgressObserver, PutOptions,
+};
+use aws_sdk_s3::types::CompletedPart;
+use std::{path::Path, sync::Arc};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    task::JoinSet,
+};
+
+/// Size of the first parts of an upload of unknown length, 8 MiB.
+///
+/// The part size doubles every 1000 parts, so that the 10000 parts of an upload can hold
+/// objects of up to 5 TiB.
+pub const UPLOAD_PART_SIZE: i64 = 8 * 1024 * 1024;
+
+// Largest part S3 accepts, 5 GiB
+const MAX_PART_SIZE: i64 = 5 * 1024 * 1024 * 1024;
+
+// Number of parts uploaded at the same time, which is also the number of parts held in memory
+pub(crate) const UPLOAD_CONCURRENCY: usize = 4;
+
+/// Result of [`Client::upload_reader`].
+#[derive(Debug, Clone)]
+pub struct UploadOutcome {
+    /// `ETag` of the object.
+    pub e_tag: Option<String>,
+    /// Number of bytes read and uploaded.
+    pub size: i64,
+    /// Number of parts of the multipart upload, 0 when the data fit in a single `PutObject`.
+    pub parts: usize,
+}
+
+impl Client {
+    /// Uploads everything read from `reader`, of which the length does not have to be known,
+    /// like stdin or the output of another process.
+    ///
+    /// Data that fits in a single part is uploaded with `PutObject`, anything larger with a
+    /// multipart upload, reading the next parts while the previous ones are uploaded. At most a
+    /// few parts are held in memory. The upload is aborted when reading or any part fails.
+    pub async fn upload_reader<R>(
+        &self,
+        destination: &ObjectLocation,
+        mut reader: R,
+        options: PutOptions,
+    ) -> Result<UploadOutcome, Error>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let first = read_part(&mut reader, upload_part_size(1)).await?;
+        if i64::try_from(first.len()).unwrap_or(i64::MAX) < upload_part_size(1) {
+            let size = i64::try_from(first.len()).unwrap_or_default();
+            let res = self
+                .put_object_stream(
+                    &destination.bucket,
+                    &destination.key,
+                    first.into(),
+                    Some(size),
+                    destination.project,
+                    options,
+                )
+                .await?;
+
+            return Ok(UploadOutcome {
+                e_tag: res.e_tag,
+                size,
+                parts: 0,
+            });
+        }
+
+        let upload_id = self
+            .create_multipart_upload(
+                &destination.bucket,
+                &destination.key,
+                destination.project,
+                options,
+            )
+            .await?;
+
+        let res = self
+            .upload_parts(destination, &upload_id, first, &mut reader)
+            .await;
+        let res = match res {
+            Ok((parts, size)) => {
+                let count = parts.len();
+                self.complete_multipart_upload(
+                    &destination.bucket,
+                    &destination.key,
+                    destination.project,
+                    &upload_id,
+                    parts,
+                )
+                .await
+                .map(|res| UploadOutcome {
+                    e_tag: res.e_tag,
+                    size,
+                    parts: count,
+                })
+            }
+            Err(e) => Err(e),
+        };
+
+        if res.is_err() {
+            if let Err(abort_err) = self
+                .abort_multipart_upload(
+                    &destination.bucket,
+                    &destination.key,
+                    destination.project,
+                    &upload_id,
+                )
+                .await
+            {
+                log::warn!("failed to abort multipart upload {upload_id}: {abort_err}");
+            }
+        }
+
+        res
+    }
+
+    /// Uploads the file at `path` with [`Client::upload_reader`] and reports the bytes read
+    /// to `progress`. Parts are uploaded while the next ones are read, so the reported bytes
+    /// run a few parts ahead of what the server received.
+    pub async fn upload_file(
+        &self,
+        path: &Path,
+        destination: &ObjectLocation,
+        options: PutOptions,
+        progress: Option<Arc<dyn ProgressObserver>>,
+    ) -> Result<UploadOutcome, Error> {
+        let io_error = |e| Error::LocalFile(path.display().to_string(), e);
+
+        let file = tokio::fs::File::open(path).await.map_err(io_error)?;
+        let reader: Box<dyn AsyncRead + Unpin + Send> = match progress {
+            Some(observer) => {
+                let total = file.metadata().await.map_err(io_error)?.len();
+                Box::new(ProgressReader::new(file, Some(total), observer))
+            }
+            None => Box::new(file),
+        };
+
+        self.upload_reader(destination, reader, options).await
+    }
+
+    // Upload `first` and the rest of the reader, at most `UPLOAD_CONCURRENCY` parts at a time
+    async fn upload_parts<R>(
+        &self,
+        destination: &ObjectLocation,
+        upload_id: &str,
+        first: Vec<u8>,
+        reader: &mut R,
+    ) -> Result<(Vec<CompletedPart>, i64), Error>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let mut tasks = JoinSet::new();
+        let mut parts = Vec::new();
+        let mut size = 0;
+        let mut data = first;
+
+        for part_number in 1.. {
+            if data.is_empty() {
+                break;
+            }
+            if i64::from(part_number) > MAX_PARTS {
+                return Err(Error::TooManyParts(MAX_PARTS));
+            }
+            size += i64::try_from(data.len()).unwrap_or_default();
+
+            // Wait for a free slot, returning early (and dropping the other uploads) on failure
+            while tasks.len() >= UPLOAD_CONCURRENCY {
+                if let Some(res) = tasks.join_next().await {
+                    parts.push(res.map_err(|e| Error::TaskFailed(e.to_string()))??);
+                }
+            }
+
+            let client = self.clone();
+            let destination = destination.clone();
+            let upload_id = upload_id.to_string();
+            tasks.spawn(async move {
+                client
+                    .upload_part(
+                        &destination.bucket,
+                        &destination.key,
+                        destination.project,
+                        &upload_id,
+                        part_number,
+                        data.into(),
+                    )
+                    .await
+            });
+
+            data = read_part(reader, upload_part_size(part_number + 1)).await?;
+        }
+
+        while let Some(res) = tasks.join_next().await {
+            parts.push(res.map_err(|e| Error::TaskFailed(e.to_string()))??);
+        }
+
+        Ok((parts, size))
+    }
+}
+
+/// Size of part `part_number` (starting at 1) of an upload of unknown length, see
+/// [`UPLOAD_PART_SIZE`].
+#[must_use]
+pub fn upload_part_size(part_number: i32) -> i64 {
+    part_size_from(UPLOAD_PART_SIZE, part_number)
+}
+
+// Size of a part of an upload whose first parts are `first_part_size`
+fn part_size_from(first_part_size: i64, part_number: i32) -> i64 {
+    let doublings = (part_number - 1).clamp(0, 9_999) / 1000;
+    (first_part_size << doublings).min(MAX_PART_SIZE)
+}
+
+// Part numbers and inclusive byte ranges of a file of `size` bytes, split like
+// `Client::upload_reader` splits its data when its first parts are `first_part_size`
+pub(crate) fn upload_part_ranges(size: i64, first_part_size: i64) -> Vec<(i32, i64, i64)> {
+    let mut ranges = Vec::new();
+    let mut first = 0;
+    for part_number in 1.. {
+        if first >= size {
+            break;
+        }
+        let last = (first + part_size_from(first_part_size, part_number)).min(size) - 1;
+        ranges.push((part_number, first, last));
+        first = last + 1;
+    }
+
+    ranges
+}
+
+// Read up to `size` bytes, less only at the end of the reader
+async fn read_part<R>(reader: &mut R, size: i64) -> Result<Vec<u8>, Error>
+where
+    R: AsyncRead + Unpin + Send,
+{
+    let mut data = Vec::new();
+    reader
+        .take(u64::try_from(size).unwrap_or_default())
+        .read_to_end(&mut data)
+        .await
+        .map_err(|e| Error::Read(e.to_string()))?;
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_part_size_grows_up_to_5_tib() {
+        assert_eq!(upload_part_size(1), UPLOAD_PART_SIZE);
+        assert_eq!(upload_part_size(1000), UPLOAD_PART_SIZE);
+        assert_eq!(upload_part_size(1001), 2 * UPLOAD_PART_SIZE);
+        assert_eq!(upload_part_size(10_000), 4 * 1024 * 1024 * 1024);
+
+        let total = (1..=10_000).map(upload_part_size).sum::<i64>();
+        assert!(total >= 5 * 1024 * 1024 * 1024 * 1024);
+    }
+
+    #[test]
+    fn upload_part_ranges_follow_the_part_sizes() {
+        const MIB: i64 = 1024 * 1024;
+
+        assert!(upload_part_ranges(0, UPLOAD_PART_SIZE).is_empty());
+        assert_eq!(
+            upload_part_ranges(8 * MIB, UPLOAD_PART_SIZE),
+            vec![(1, 0, 8 * MIB - 1)]
+        );
+        assert_eq!(
+            upload_part_ranges(20 * MIB, UPLOAD_PART_SIZE),
+            vec![
+                (1, 0, 8 * MIB - 1),
+                (2, 8 * MIB, 16 * MIB - 1),
+                (3, 16 * MIB, 20 * MIB - 1)
+            ]
+        );
+        let ranges = upload_part_ranges(1001 * 8 * MIB + 1, UPLOAD_PART_SIZE);
+        assert_eq!(ranges[1000], (1001, 1000 * 8 * MIB, 1001 * 8 * MIB));
+        // Uploads started with other part sizes keep them
+        assert_eq!(
+            upload_part_ranges(20 * MIB, 16 * MIB),
+            vec![(1, 0, 16 * MIB - 1), (2, 16 * MIB, 20 * MIB - 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn read_part_stops_at_the_end() {
+        let mut reader: &[u8] = b"abcdefg";
+
+        assert_eq!(read_part(&mut reader, 3).await.unwrap(), b"abc");
+        assert_eq!(read_part(&mut reader, 3).await.unwrap(), b"def");
+        assert_eq!(read_part(&mut reader, 3).await.unwrap(), b"g");
+        assert!(read_part(&mut reader, 3).await.unwrap().is_empty());
+    }
+}